@@ -0,0 +1,34 @@
+//! Compares fast queries in `Standard` mode whose line is disabled, which
+//! are not rendered, with the same queries when their SQL is tracked.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use diesel::sqlite::SqliteConnection;
+use diesel_logger::{DbLogMode, LoggingConnection};
+
+fn connection(track_last_sql: bool) -> LoggingConnection<SqliteConnection> {
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    let mut conn = LoggingConnection::new(conn, DbLogMode::Standard);
+    conn.set_track_last_sql(track_last_sql);
+    conn
+}
+
+fn fast_queries(c: &mut Criterion) {
+    let mut group = c.benchmark_group("standard_fast_query");
+    for (name, track_last_sql) in [("line_disabled", false), ("sql_tracked", true)] {
+        let conn = connection(track_last_sql);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                diesel::select(sql::<Integer>("1"))
+                    .get_result::<i32>(&conn)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fast_queries);
+criterion_main!(benches);
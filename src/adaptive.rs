@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::fingerprint::fingerprint;
+
+/// At most this many fingerprints are tracked per connection,
+/// further query shapes are not checked against a baseline.
+const MAX_FINGERPRINTS: usize = 10_000;
+
+/// Configuration for flagging queries as slow relative to their own history
+/// instead of a fixed threshold.
+///
+/// For every query shape the mean and standard deviation of its durations are
+/// kept. A query is considered slow if it takes longer than
+/// `mean + sigma * stddev` of its shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveThresholds {
+    /// How many standard deviations above the mean a query has to be.
+    pub sigma: f64,
+    /// How many executions of a query shape are needed before the rule applies.
+    pub min_samples: u64,
+    /// Roughly how many of the most recent executions the baseline covers.
+    pub window: u64,
+}
+
+impl Default for AdaptiveThresholds {
+    fn default() -> Self {
+        AdaptiveThresholds {
+            sigma: 3.0,
+            min_samples: 30,
+            window: 100,
+        }
+    }
+}
+
+/// Configuration for flagging queries as slow when they take a multiple of the
+/// exponential moving average of their own query shape.
+///
+/// This catches a query which normally takes 5 ms suddenly taking 50 ms,
+/// which is still fast by any fixed threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovingAverageThreshold {
+    /// A query is slow if it takes longer than `factor` times its average.
+    pub factor: f64,
+    /// Weight of the newest sample in the moving average, between 0 and 1.
+    pub smoothing: f64,
+    /// How many executions of a query shape are needed before the rule applies.
+    pub min_samples: u64,
+}
+
+impl Default for MovingAverageThreshold {
+    fn default() -> Self {
+        MovingAverageThreshold {
+            factor: 3.0,
+            smoothing: 0.1,
+            min_samples: 10,
+        }
+    }
+}
+
+/// Rolling duration statistics of one query shape, in seconds.
+#[derive(Default)]
+struct Baseline {
+    count: u64,
+    mean: f64,
+    variance: f64,
+    moving_average: f64,
+}
+
+impl Baseline {
+    /// Exponentially weighted update, which is the exact running mean and
+    /// variance until `window` samples have been seen.
+    fn add(&mut self, sample: f64, window: u64, smoothing: f64) {
+        self.count += 1;
+        let alpha = 1.0 / self.count.min(window.max(1)) as f64;
+        let diff = sample - self.mean;
+        let increment = alpha * diff;
+        self.mean += increment;
+        self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+
+        self.moving_average = if self.count == 1 {
+            sample
+        } else {
+            smoothing * sample + (1.0 - smoothing) * self.moving_average
+        };
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AdaptiveBaselines {
+    deviation: Option<AdaptiveThresholds>,
+    moving_average: Option<MovingAverageThreshold>,
+    baselines: Mutex<HashMap<u64, Baseline>>,
+}
+
+impl AdaptiveBaselines {
+    pub(crate) fn set_deviation(&mut self, config: Option<AdaptiveThresholds>) {
+        self.deviation = config;
+        self.baselines().clear();
+    }
+
+    pub(crate) fn set_moving_average(&mut self, config: Option<MovingAverageThreshold>) {
+        self.moving_average = config;
+        self.baselines().clear();
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.deviation.is_some() || self.moving_average.is_some()
+    }
+
+    /// Adds the duration to the baseline of the query shape.
+    /// Returns the lowest limit of the baseline the query exceeded, if any.
+    pub(crate) fn observe(&self, shape: &str, duration: Duration) -> Option<Duration> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let key = fingerprint(shape);
+        let sample = duration.as_secs_f64();
+
+        let mut baselines = self.baselines();
+        if baselines.len() >= MAX_FINGERPRINTS && !baselines.contains_key(&key) {
+            return None;
+        }
+        let baseline = baselines.entry(key).or_default();
+
+        let deviation_limit = self
+            .deviation
+            .filter(|config| baseline.count >= config.min_samples)
+            .map(|config| baseline.mean + config.sigma * baseline.variance.sqrt());
+        let average_limit = self
+            .moving_average
+            .filter(|config| baseline.count >= config.min_samples)
+            .map(|config| config.factor * baseline.moving_average);
+        let exceeded = deviation_limit
+            .into_iter()
+            .chain(average_limit)
+            .filter(|limit| sample > *limit)
+            .reduce(f64::min);
+
+        let window = self.deviation.unwrap_or_default().window;
+        let smoothing = self.moving_average.unwrap_or_default().smoothing;
+        baseline.add(sample, window, smoothing);
+
+        exceeded.map(Duration::from_secs_f64)
+    }
+
+    fn baselines(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Baseline>> {
+        self.baselines.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::fingerprint::fingerprint;
+
+/// At most this many query shapes are aggregated per connection.
+const MAX_SHAPES: usize = 10_000;
+
+/// Accumulated timings of all executions of one query shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryAggregate {
+    /// The query normalized as configured, by default with its literals and
+    /// bind values stripped.
+    pub shape: String,
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct QueryAggregates {
+    enabled: bool,
+    aggregates: Mutex<HashMap<u64, QueryAggregate>>,
+}
+
+impl QueryAggregates {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.aggregates().clear();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn add(&self, shape: &str, duration: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let key = fingerprint(shape);
+        let mut aggregates = self.aggregates();
+        if aggregates.len() >= MAX_SHAPES && !aggregates.contains_key(&key) {
+            return;
+        }
+        let aggregate = aggregates.entry(key).or_insert_with(|| QueryAggregate {
+            shape: shape.to_owned(),
+            count: 0,
+            total: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        });
+        aggregate.count += 1;
+        aggregate.total += duration;
+        aggregate.max = aggregate.max.max(duration);
+    }
+
+    /// Returns all aggregates, the ones with the highest total time first.
+    pub(crate) fn snapshot(&self) -> Vec<QueryAggregate> {
+        let mut aggregates = self.aggregates().values().cloned().collect::<Vec<_>>();
+        aggregates.sort_by_key(|a| std::cmp::Reverse(a.total));
+        aggregates
+    }
+
+    fn aggregates(&self) -> std::sync::MutexGuard<'_, HashMap<u64, QueryAggregate>> {
+        self.aggregates.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Renders the `top` aggregates with the highest total time.
+pub(crate) fn report(
+    aggregates: &[QueryAggregate],
+    top: usize,
+    precision: Option<usize>,
+) -> String {
+    let mut report = format!(
+        "Query report, top {} of {} query shapes by total time:",
+        top.min(aggregates.len()),
+        aggregates.len()
+    );
+    for aggregate in aggregates.iter().take(top) {
+        report.push_str(&format!(
+            "\n    {}x, total {}, max {}: {}",
+            aggregate.count,
+            crate::format_duration_auto(aggregate.total, precision),
+            crate::format_duration_auto(aggregate.max, precision),
+            aggregate.shape
+        ));
+    }
+    report
+}
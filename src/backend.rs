@@ -0,0 +1,28 @@
+/// The short name of a diesel backend, e.g. `pg` for `diesel::pg::Pg`, which
+/// is only displayed. Backends of other crates are named after their type,
+/// e.g. `Oracle` for `diesel_oci::oracle::Oracle`.
+pub(crate) fn backend_name<B>() -> &'static str {
+    let type_name = std::any::type_name::<B>();
+    let path = type_name.split('<').next().unwrap_or(type_name);
+    let name = path.rsplit("::").next().unwrap_or(path);
+    match name {
+        "Pg" => "pg",
+        "Mysql" => "mysql",
+        "Sqlite" => "sqlite",
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Oracle;
+
+    #[test]
+    fn diesel_backends_have_short_names() {
+        assert_eq!(backend_name::<diesel::sqlite::Sqlite>(), "sqlite");
+        assert_eq!(backend_name::<Oracle>(), "Oracle");
+        assert_eq!(backend_name::<Option<Oracle>>(), "Option");
+    }
+}
@@ -0,0 +1,56 @@
+/// Splits the SQL of a `batch_execute` into its statements at the `;` which
+/// are not inside string literals, quoted identifiers, dollar quoted strings
+/// or comments. Statements which are empty or only comments are left out.
+pub(crate) fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"') => i = skip_past(bytes, i + 1, &[quote]),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_past(bytes, i + 2, b"\n"),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_past(bytes, i + 2, b"*/"),
+            b'$' => match dollar_tag(&bytes[i..]) {
+                Some(tag) => i = skip_past(bytes, i + tag.len(), tag),
+                None => i += 1,
+            },
+            b';' => {
+                statements.push(&sql[start..i]);
+                i += 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    statements.push(&sql[start..]);
+    statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|statement| !crate::comments::strip_comments(statement).trim().is_empty())
+        .collect()
+}
+
+/// Returns the position after the next occurrence of `end` from `from` on,
+/// or the end of `bytes`.
+fn skip_past(bytes: &[u8], from: usize, end: &[u8]) -> usize {
+    bytes
+        .get(from..)
+        .and_then(|rest| rest.windows(end.len()).position(|w| w == end))
+        .map_or(bytes.len(), |pos| from + pos + end.len())
+}
+
+/// Returns the tag which starts a dollar quoted string, e.g. `$$` or `$body$`.
+/// Positional parameters like `$1` are no tags.
+fn dollar_tag(bytes: &[u8]) -> Option<&[u8]> {
+    let len = bytes[1..]
+        .iter()
+        .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))?;
+    let tag = &bytes[..len + 2];
+    let is_tag = tag[len + 1] == b'$' && !matches!(tag.get(1), Some(b) if b.is_ascii_digit());
+    if is_tag {
+        Some(tag)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+
+use crate::truncate::truncate_bytes;
+
+/// Shortens every bind value in the `-- binds: [...]` trailer of `query` whose
+/// `Debug` output is longer than `max_bytes`, keeping the SQL itself intact.
+/// Byte arrays become `<N bytes>`, strings are cut and end with `…`.
+pub(crate) fn cap_binds(query: &str, max_bytes: usize) -> Cow<'_, str> {
+    let pos = match query.find(" -- binds: ") {
+        Some(pos) => pos + " -- binds: ".len(),
+        None => return Cow::Borrowed(query),
+    };
+    let binds = split_binds(&query[pos..]);
+    if binds.iter().all(|bind| bind.len() <= max_bytes) {
+        return Cow::Borrowed(query);
+    }
+    let binds = binds
+        .into_iter()
+        .map(|bind| cap_bind(bind, max_bytes))
+        .collect::<Vec<_>>();
+    Cow::Owned(format!("{}[{}]", &query[..pos], binds.join(", ")))
+}
+
+fn cap_bind(bind: &str, max_bytes: usize) -> Cow<'_, str> {
+    if bind.len() <= max_bytes {
+        return Cow::Borrowed(bind);
+    }
+    if let Some(bytes) = bind.strip_prefix('[').and_then(|b| b.strip_suffix(']')) {
+        if bytes.split(',').all(|b| b.trim().parse::<u8>().is_ok()) {
+            return Cow::Owned(format!("<{} bytes>", bytes.split(',').count()));
+        }
+    }
+    let cut = truncate_bytes(bind, max_bytes);
+    if bind.starts_with('"') {
+        Cow::Owned(format!("{}\"", cut))
+    } else {
+        cut
+    }
+}
+
+/// The number of bind parameters of a query rendered by `debug_query`, or
+/// `None` for SQL without a `-- binds: [...]` trailer, e.g. of `execute`.
+pub(crate) fn bind_count(query: &str) -> Option<usize> {
+    let pos = query.find(" -- binds: ")?;
+    Some(split_binds(&query[pos + " -- binds: ".len()..]).len())
+}
+
+/// Splits the `Debug` output of the bind list, e.g. `[1, "a, b"]`,
+/// into its elements.
+pub(crate) fn split_binds(list: &str) -> Vec<&str> {
+    let list = list.trim();
+    let list = match list.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        Some(list) => list,
+        None => return Vec::new(),
+    };
+    let mut binds = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                binds.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !list[start..].trim().is_empty() {
+        binds.push(list[start..].trim());
+    }
+    binds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_are_split_at_top_level_commas() {
+        assert_eq!(
+            split_binds(r#"[1, "a, \"b\"", Some((2, 3)), [4, 5], None]"#),
+            ["1", r#""a, \"b\"""#, "Some((2, 3))", "[4, 5]", "None"]
+        );
+        assert!(split_binds("[]").is_empty());
+        assert!(split_binds("no list").is_empty());
+    }
+
+    #[test]
+    fn long_binds_are_capped() {
+        let query = r#"INSERT INTO t VALUES (?, ?, ?) -- binds: [[1, 2, 3, 4], "abcdefgh", 7]"#;
+        assert_eq!(
+            cap_binds(query, 6),
+            r#"INSERT INTO t VALUES (?, ?, ?) -- binds: [<4 bytes>, "abcde…", 7]"#
+        );
+        assert!(matches!(cap_binds(query, 100), Cow::Borrowed(_)));
+        assert!(matches!(cap_binds("SELECT 1", 1), Cow::Borrowed(_)));
+    }
+}
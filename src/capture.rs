@@ -0,0 +1,105 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Records the SQL of every query while `LoggingConnection::capture_queries` runs.
+#[derive(Default)]
+pub(crate) struct QueryCapture {
+    active: AtomicBool,
+    queries: Mutex<Vec<String>>,
+}
+
+impl QueryCapture {
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record(&self, query: &str) {
+        self.queries().push(query.to_owned());
+    }
+
+    /// Runs `f` and returns the queries recorded meanwhile. An outer capture
+    /// gets the queries of a nested one as well.
+    pub(crate) fn capture<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<String>) {
+        let outer = std::mem::take(&mut *self.queries());
+        let was_active = self.active.swap(true, Ordering::Relaxed);
+        let guard = RestoreOnDrop {
+            capture: self,
+            outer,
+            was_active,
+        };
+        let result = f();
+        let captured = self.queries().clone();
+        drop(guard);
+        (result, captured)
+    }
+
+    fn queries(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+        self.queries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Restores the state of an outer capture, even if the closure panics.
+struct RestoreOnDrop<'a> {
+    capture: &'a QueryCapture,
+    outer: Vec<String>,
+    was_active: bool,
+}
+
+impl Drop for RestoreOnDrop<'_> {
+    fn drop(&mut self) {
+        let mut queries = self.capture.queries();
+        let captured = std::mem::replace(&mut *queries, std::mem::take(&mut self.outer));
+        if self.was_active {
+            queries.extend(captured);
+        }
+        self.capture
+            .active
+            .store(self.was_active, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_queries_run_by_the_closure_are_captured() {
+        let capture = QueryCapture::default();
+        assert!(!capture.is_active());
+        let (result, queries) = capture.capture(|| {
+            assert!(capture.is_active());
+            capture.record("SELECT 1");
+            42
+        });
+        assert_eq!(result, 42);
+        assert_eq!(queries, ["SELECT 1"]);
+        assert!(!capture.is_active());
+    }
+
+    #[test]
+    fn outer_captures_get_the_queries_of_nested_ones() {
+        let capture = QueryCapture::default();
+        let ((_, inner), outer) = capture.capture(|| {
+            capture.record("SELECT 1");
+            let inner = capture.capture(|| capture.record("SELECT 2"));
+            capture.record("SELECT 3");
+            inner
+        });
+        assert_eq!(inner, ["SELECT 2"]);
+        assert_eq!(outer, ["SELECT 1", "SELECT 2", "SELECT 3"]);
+    }
+
+    #[test]
+    fn a_panicking_closure_ends_the_capture() {
+        let capture = QueryCapture::default();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            capture.capture(|| {
+                capture.record("SELECT 1");
+                panic!("boom");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert!(!capture.is_active());
+        assert_eq!(capture.capture(|| ()).1, Vec::<String>::new());
+    }
+}
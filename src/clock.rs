@@ -0,0 +1,155 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where a `LoggingConnection` takes the wall-clock time of its log lines and
+/// events from. Inject a fixed clock to make timestamps deterministic in tests.
+///
+/// Only timestamps come from here, durations are always measured with `Instant`.
+pub trait TimeSource: fmt::Debug + Send + Sync {
+    /// The current wall-clock time. It is called once per query, before the
+    /// query runs, and on the thread which runs it, so it should be cheap.
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock, used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Returns the current time of `source` as used in log lines.
+pub(crate) fn now(source: &dyn TimeSource) -> Timestamp {
+    Timestamp(source.now())
+}
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// A wall-clock time, shown in UTC like `2024-05-01 12:00:00.250 UTC`.
+/// Fractions of a second are left out if they are zero, and shown with 3, 6 or
+/// 9 digits otherwise, as few as needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Timestamp(pub(crate) SystemTime);
+
+impl Timestamp {
+    /// The seconds since the Unix epoch, negative before it, and the
+    /// nanoseconds within that second.
+    fn unix(self) -> (i64, u32) {
+        match self.0.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                let secs = -(before.as_secs() as i64);
+                match before.subsec_nanos() {
+                    0 => (secs, 0),
+                    nanos => (secs - 1, 1_000_000_000 - nanos),
+                }
+            }
+        }
+    }
+
+    /// The time since midnight UTC.
+    pub(crate) fn time_of_day(self) -> Duration {
+        let (secs, nanos) = self.unix();
+        Duration::new(secs.rem_euclid(SECS_PER_DAY) as u64, nanos)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (secs, nanos) = self.unix();
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let secs_of_day = secs.rem_euclid(SECS_PER_DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )?;
+        match nanos {
+            0 => {}
+            _ if nanos % 1_000_000 == 0 => write!(f, ".{:03}", nanos / 1_000_000)?,
+            _ if nanos % 1_000 == 0 => write!(f, ".{:06}", nanos / 1_000)?,
+            _ => write!(f, ".{:09}", nanos)?,
+        }
+        f.write_str(" UTC")
+    }
+}
+
+/// The year, month and day of the proleptic Gregorian calendar `days` after
+/// 1970-01-01, after Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64, nanos: u32) -> Timestamp {
+        Timestamp(UNIX_EPOCH + Duration::new(secs, nanos))
+    }
+
+    #[test]
+    fn timestamps_are_shown_in_utc() {
+        assert_eq!(at(0, 0).to_string(), "1970-01-01 00:00:00 UTC");
+        assert_eq!(at(951_782_400, 0).to_string(), "2000-02-29 00:00:00 UTC");
+        assert_eq!(
+            at(1_714_564_800, 250_000_000).to_string(),
+            "2024-05-01 12:00:00.250 UTC"
+        );
+        assert_eq!(
+            at(4_102_444_799, 1_000).to_string(),
+            "2099-12-31 23:59:59.000001 UTC"
+        );
+        assert_eq!(at(59, 7).to_string(), "1970-01-01 00:00:59.000000007 UTC");
+    }
+
+    #[test]
+    fn timestamps_before_the_epoch() {
+        let before = Timestamp(UNIX_EPOCH - Duration::from_millis(1500));
+        assert_eq!(before.to_string(), "1969-12-31 23:59:58.500 UTC");
+        assert_eq!(before.time_of_day(), Duration::from_millis(86_398_500));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn timestamps_are_shown_like_chrono() {
+        for secs in (0..4_000_000_000).step_by(99_999_989) {
+            for &nanos in &[0, 120_000_000, 123_456_000, 123_456_789] {
+                let time = UNIX_EPOCH + Duration::new(secs, nanos);
+                let chrono: chrono::DateTime<chrono::Utc> = time.into();
+                assert_eq!(Timestamp(time).to_string(), chrono.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn time_of_day() {
+        assert_eq!(
+            at(1_714_564_800 + 90, 5).time_of_day(),
+            Duration::new(12 * 3600 + 90, 5)
+        );
+    }
+}
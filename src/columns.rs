@@ -0,0 +1,118 @@
+/// Returns the names of the columns a `SELECT` returns, taken from its select
+/// list: the alias if there is one, otherwise the unqualified column name,
+/// otherwise the expression itself. A `*` is kept as is, which is the point.
+///
+/// Returns `None` for anything which does not start with `SELECT`, e.g. CTEs,
+/// as the columns are not known without asking the database.
+pub(crate) fn selected_columns(query: &str) -> Option<Vec<String>> {
+    let query = match query.find(" -- binds: ") {
+        Some(pos) => &query[..pos],
+        None => query,
+    };
+    let query = query.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+    let list = strip_keyword(query, "SELECT")?;
+    let list = strip_keyword(list, "DISTINCT").unwrap_or(list);
+
+    let mut columns = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    let mut end = list.len();
+    for (i, c) in list.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' | '`' => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    // The end of the subquery the select started in.
+                    end = i;
+                    break;
+                }
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    columns.push(column_name(&list[start..i]));
+                    start = i + 1;
+                }
+                _ if depth == 0 && is_keyword_at(list, i, "FROM") => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            },
+        }
+    }
+    columns.push(column_name(&list[start..end]));
+    Some(columns)
+}
+
+/// Names one item of a select list.
+fn column_name(item: &str) -> String {
+    let item = item.trim();
+    let name = match find_alias(item) {
+        Some(alias) => alias,
+        None if item.ends_with(|c: char| c == '"' || c == '`' || is_identifier_char(c)) => {
+            let last = item.rsplit('.').next().unwrap_or(item);
+            if last.contains(|c: char| c.is_whitespace() || c == '(') {
+                item
+            } else {
+                last
+            }
+        }
+        None => item,
+    };
+    name.trim_matches(|c| c == '"' || c == '`')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the alias after the last top level `AS` of a select list item.
+fn find_alias(item: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut alias = None;
+    for (i, c) in item.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' | '`' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                _ if depth == 0 && is_keyword_at(item, i, "AS") => {
+                    alias = Some(item[i + 2..].trim());
+                }
+                _ => {}
+            },
+        }
+    }
+    alias.filter(|alias| !alias.is_empty())
+}
+
+/// Returns what follows `keyword` if `query` starts with it.
+fn strip_keyword<'a>(query: &'a str, keyword: &str) -> Option<&'a str> {
+    if is_keyword_at(query, 0, keyword) {
+        Some(&query[keyword.len()..])
+    } else {
+        None
+    }
+}
+
+/// Returns true if the word `keyword` starts at byte `i` of `query`.
+fn is_keyword_at(query: &str, i: usize, keyword: &str) -> bool {
+    let bytes = query.as_bytes();
+    bytes.len() >= i + keyword.len()
+        && bytes[i..i + keyword.len()].eq_ignore_ascii_case(keyword.as_bytes())
+        && (i == 0 || !is_identifier_byte(bytes[i - 1]))
+        && !matches!(bytes.get(i + keyword.len()), Some(&b) if is_identifier_byte(b))
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
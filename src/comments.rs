@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+
+/// Removes `--` line comments and `/* */` block comments from the SQL of a query,
+/// leaving string literals, quoted identifiers and the `-- binds: [...]` trailer
+/// `debug_query` appends untouched. Every comment is replaced by a single space.
+pub(crate) fn strip_comments(query: &str) -> Cow<'_, str> {
+    let (sql, binds) = match query.find(" -- binds: ") {
+        Some(pos) => query.split_at(pos),
+        None => (query, ""),
+    };
+    if !sql.contains("--") && !sql.contains("/*") {
+        return Cow::Borrowed(query);
+    }
+
+    let mut stripped = String::with_capacity(query.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                // A doubled quote inside the literal just closes and reopens it.
+                stripped.push(c);
+                for inner in chars.by_ref() {
+                    stripped.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for inner in chars.by_ref() {
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+                push_separator(&mut stripped, &mut chars);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // Postgres allows nested block comments.
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some('*') if chars.peek() == Some(&'/') => {
+                            chars.next();
+                            depth -= 1;
+                        }
+                        Some('/') if chars.peek() == Some(&'*') => {
+                            chars.next();
+                            depth += 1;
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                push_separator(&mut stripped, &mut chars);
+            }
+            _ => stripped.push(c),
+        }
+    }
+
+    let trimmed_len = stripped.trim_end().len();
+    stripped.truncate(trimmed_len);
+    let stripped = stripped.trim_start();
+    Cow::Owned(format!("{}{}", stripped, binds))
+}
+
+/// Keeps the tokens around a removed comment apart by exactly one space.
+fn push_separator(stripped: &mut String, chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if !stripped.ends_with(char::is_whitespace) {
+        stripped.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_become_a_single_space() {
+        assert_eq!(
+            strip_comments("SELECT a, -- the id\n  b /* the name */ FROM t"),
+            "SELECT a, b FROM t"
+        );
+        assert_eq!(strip_comments("SELECT/**/a"), "SELECT a");
+    }
+
+    #[test]
+    fn nested_block_comments_are_removed_as_a_whole() {
+        assert_eq!(strip_comments("SELECT /* a /* b */ c */ 1"), "SELECT 1");
+    }
+
+    #[test]
+    fn literals_and_the_binds_trailer_are_kept() {
+        assert_eq!(
+            strip_comments("SELECT '--', \"/*\" FROM t -- binds: [1]"),
+            "SELECT '--', \"/*\" FROM t -- binds: [1]"
+        );
+        assert_eq!(
+            strip_comments("SELECT 1 -- note -- binds: [2]"),
+            "SELECT 1 -- binds: [2]"
+        );
+    }
+
+    #[test]
+    fn queries_without_comments_are_borrowed() {
+        assert!(matches!(strip_comments("SELECT 1"), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,452 @@
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::clock::{SystemClock, TimeSource};
+use crate::format::LogFormat;
+use crate::normalize::NormalizationConfig;
+use crate::output::{LineFields, LogOutput};
+use crate::thresholds::{LogLevels, ModeThresholds, SlowQueryThresholds};
+use crate::DbLogMode;
+
+/// Options which control how the queries of a `LoggingConnection` are logged:
+/// everything its `set_*` methods configure, except for the statistics and
+/// callbacks. Take it from a configured connection with `LoggingConnection::config`.
+#[derive(Debug, Clone)]
+pub struct LoggingConfig {
+    /// Log a summary instead of queries longer than this many bytes.
+    pub(crate) summarize_query_bytes: Option<usize>,
+    /// Cut logged queries down to at most this many bytes.
+    pub(crate) max_query_bytes: Option<usize>,
+    /// Shorten bind values longer than this many bytes.
+    pub(crate) max_bind_bytes: Option<usize>,
+    /// The levels of the lines logged in `Standard` mode.
+    pub(crate) levels: LogLevels,
+    /// Remove SQL comments from logged queries.
+    pub(crate) strip_comments: bool,
+    /// Replace the literals and bind values of logged queries with `?`.
+    pub(crate) redact_literals: bool,
+    /// Only log one in this many of the queries which are not slow.
+    pub(crate) sample_fast_queries: Option<u32>,
+    /// How the line of a query is formatted.
+    pub(crate) format: LogFormat,
+    /// The wording of human readable slow query lines.
+    pub(crate) slow_query_template: Option<String>,
+    /// The slow query thresholds of every log mode.
+    pub(crate) thresholds: ModeThresholds,
+    /// Log queries this many times over the `warn` threshold at `error`.
+    pub(crate) critical_multiplier: Option<u32>,
+    /// Only log queries run at least this deep in transactions.
+    pub(crate) min_transaction_depth: Option<u32>,
+    /// Log a separate line for queries which failed on a dead connection.
+    pub(crate) flag_dead_connections: bool,
+    /// Log how long outermost transactions were open.
+    pub(crate) log_transaction_duration: bool,
+    /// Warn about commits which take at least this long.
+    pub(crate) slow_commit_threshold: Option<Duration>,
+    /// Warn about queries which took close to the statement timeout.
+    pub(crate) timeout_budget: Option<Duration>,
+    /// The fraction of the timeout budget from which queries are warned about.
+    pub(crate) timeout_warn_fraction: f64,
+    /// How queries are normalized into shapes.
+    pub(crate) normalization: NormalizationConfig,
+    /// Log queries as statements ready to be pasted into `psql`.
+    pub(crate) psql_ready: bool,
+    /// Append the `QueryId` or fingerprint of a query to its log line.
+    pub(crate) log_query_id: bool,
+    /// Round logged durations to the nearest multiple of this.
+    pub(crate) duration_bucket: Option<Duration>,
+    /// Decimals of logged durations, `None` for the defaults per unit.
+    pub(crate) duration_precision: Option<usize>,
+    /// Append the estimated size of the returned rows to slow query lines.
+    pub(crate) estimate_result_bytes: bool,
+    /// Append the columns a `SELECT` returns to its log line.
+    pub(crate) log_columns: bool,
+    /// Append the number of bind parameters to the line of a query.
+    pub(crate) show_param_count: bool,
+    /// Constant `key=value` pairs appended to the line of every query.
+    pub(crate) context: Vec<(String, String)>,
+    /// Append `dur_ms` and `rows` tokens to human readable lines.
+    pub(crate) append_machine_fields: bool,
+    /// Prefix the lines printed in the `Excessive` modes and written to
+    /// writers with their timestamp.
+    pub(crate) show_timestamp: bool,
+    /// Only log the failed queries of migrations.
+    pub(crate) suppress_migration_logs: bool,
+    /// Log every query before it is run, not only afterwards.
+    pub(crate) log_before_execute: bool,
+    /// Only log queries started between these times since midnight UTC.
+    pub(crate) active_window: Option<(Duration, Duration)>,
+    /// Estimate the cost of queries from this duration on, and show it from this cost on.
+    pub(crate) cost_estimates: Option<(Duration, f64)>,
+    /// Log this many of the slowest queries every so often.
+    pub(crate) worst_queries_digest: Option<(usize, Duration)>,
+    /// Warn about updates and deletes which affected no rows.
+    pub(crate) warn_on_zero_affected: bool,
+    /// Warn about updates and deletes which affected more rows than this.
+    pub(crate) max_affected_rows: Option<usize>,
+    /// Warn about updates and deletes without a `WHERE` clause.
+    pub(crate) warn_on_missing_where: bool,
+    /// Refuse to run updates and deletes without a `WHERE` clause.
+    pub(crate) refuse_missing_where: bool,
+    /// Record the SQL of failed queries on the current `tracing` span.
+    #[cfg(feature = "tracing-events")]
+    pub(crate) record_failed_query_on_span: bool,
+    /// Mark the line of every query with its sequence number.
+    pub(crate) show_seq: bool,
+    /// Mark the lines of hand-written SQL.
+    pub(crate) mark_raw_queries: bool,
+    /// Mark every line with the backend of the connection.
+    pub(crate) show_backend: bool,
+    /// The name of the backend of the connection, e.g. `pg`.
+    pub(crate) backend: &'static str,
+    /// Where the timestamps of log lines come from.
+    pub(crate) clock: Arc<dyn TimeSource>,
+    /// Where the log lines are written to.
+    pub(crate) output: Arc<LogOutput>,
+    /// Where the lines of failed queries go, if not to `output`.
+    pub(crate) error_output: Option<Arc<LogOutput>>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            summarize_query_bytes: None,
+            max_query_bytes: None,
+            max_bind_bytes: None,
+            levels: LogLevels::default(),
+            strip_comments: false,
+            redact_literals: false,
+            sample_fast_queries: None,
+            format: LogFormat::Text,
+            slow_query_template: None,
+            thresholds: ModeThresholds::default(),
+            critical_multiplier: Some(5),
+            min_transaction_depth: None,
+            flag_dead_connections: true,
+            log_transaction_duration: false,
+            slow_commit_threshold: None,
+            timeout_budget: None,
+            timeout_warn_fraction: 0.9,
+            normalization: NormalizationConfig::default(),
+            psql_ready: false,
+            log_query_id: false,
+            duration_bucket: None,
+            duration_precision: None,
+            estimate_result_bytes: false,
+            log_columns: false,
+            show_param_count: false,
+            context: Vec::new(),
+            append_machine_fields: false,
+            show_timestamp: true,
+            suppress_migration_logs: false,
+            log_before_execute: false,
+            active_window: None,
+            cost_estimates: None,
+            worst_queries_digest: None,
+            warn_on_zero_affected: false,
+            max_affected_rows: None,
+            warn_on_missing_where: false,
+            refuse_missing_where: false,
+            #[cfg(feature = "tracing-events")]
+            record_failed_query_on_span: false,
+            show_seq: false,
+            mark_raw_queries: false,
+            show_backend: false,
+            backend: "",
+            clock: Arc::new(SystemClock),
+            output: Arc::new(LogOutput::Log),
+            error_output: None,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Opinionated defaults for development, where every query should be seen:
+    ///
+    /// - slow from 50 ms and very slow from 500 ms in every mode
+    /// - in `Standard` mode, queries at `info` and slow ones at `warn`
+    /// - human readable lines with the sequence number of every query
+    /// - queries ready to be pasted into `psql`
+    /// - how long transactions were open
+    /// - warnings about updates and deletes which affected no rows
+    ///
+    /// Everything else is the default. Adopt it with `LoggingConnection::set_config`,
+    /// `set_global_defaults` or `LoggingConnectionManager::with_config`.
+    pub fn development() -> Self {
+        let mut config = LoggingConfig::default();
+        config.thresholds.set_all(SlowQueryThresholds {
+            slow: Duration::from_millis(50),
+            very_slow: Duration::from_millis(500),
+        });
+        config.levels = LogLevels {
+            normal: log::Level::Info,
+            slow: log::Level::Warn,
+            very_slow: log::Level::Warn,
+        };
+        config.format = LogFormat::Text;
+        config.show_seq = true;
+        config.psql_ready = true;
+        config.log_transaction_duration = true;
+        config.warn_on_zero_affected = true;
+        config
+    }
+
+    /// Opinionated defaults for production, where only slow queries should be
+    /// logged, in a form log pipelines can parse:
+    ///
+    /// - slow from 1 s and very slow from 5 s in every mode
+    /// - in `Standard` mode, queries at `trace` and slow ones at `warn`;
+    ///   queries five times over the very slow threshold still escalate to `error`
+    /// - logfmt lines with the `QueryId` or fingerprint of every query
+    /// - literals and bind values replaced by `?`, so no data ends up in logs
+    /// - one in 100 of the queries which are not slow, failed ones excepted
+    /// - queries cut to 4 KB and queries over 64 KB summarized
+    ///
+    /// Everything else is the default. To also cap the number of lines, see
+    /// `set_max_logs_per_sec`.
+    pub fn production() -> Self {
+        let mut config = LoggingConfig::default();
+        config.thresholds.set_all(SlowQueryThresholds::default());
+        config.levels = LogLevels {
+            normal: log::Level::Trace,
+            slow: log::Level::Warn,
+            very_slow: log::Level::Warn,
+        };
+        config.format = LogFormat::Logfmt;
+        config.log_query_id = true;
+        config.redact_literals = true;
+        config.sample_fast_queries = Some(100);
+        config.max_query_bytes = Some(4 * 1024);
+        config.summarize_query_bytes = Some(64 * 1024);
+        config
+    }
+
+    /// The preset `GST_DATABASE_LOGGING` names: `development` for `dev` or
+    /// `development`, `production` for `prod` or `production`, and the
+    /// defaults otherwise. New connections start with it unless global
+    /// defaults were set, and `DbLogMode::from_env` logs in `Standard` mode
+    /// for these values.
+    pub fn from_env() -> Self {
+        let preset = std::env::var("GST_DATABASE_LOGGING").unwrap_or_default();
+        match preset.to_lowercase().as_str() {
+            "dev" | "development" => LoggingConfig::development(),
+            "prod" | "production" => LoggingConfig::production(),
+            _ => LoggingConfig::default(),
+        }
+    }
+
+    /// Whether queries started at `time` are logged. A window whose start is
+    /// after its end spans midnight.
+    pub(crate) fn is_in_active_window(&self, time: crate::clock::Timestamp) -> bool {
+        let (start, end) = match self.active_window {
+            Some(window) => window,
+            None => return true,
+        };
+        let time = time.time_of_day();
+        if start <= end {
+            start <= time && time < end
+        } else {
+            start <= time || time < end
+        }
+    }
+
+    /// Emits a line about a query to the output.
+    pub(crate) fn emit(
+        &self,
+        level: log::Level,
+        mode: DbLogMode,
+        start_time: crate::clock::Timestamp,
+        message: &str,
+        fields: Option<&LineFields<'_>>,
+    ) {
+        self.emit_to(&self.output, level, mode, start_time, message, fields);
+    }
+
+    /// Emits a line about a failed query to the error output.
+    pub(crate) fn emit_failure(
+        &self,
+        level: log::Level,
+        mode: DbLogMode,
+        start_time: crate::clock::Timestamp,
+        message: &str,
+        fields: Option<&LineFields<'_>>,
+    ) {
+        self.emit_to(
+            self.failure_output(),
+            level,
+            mode,
+            start_time,
+            message,
+            fields,
+        );
+    }
+
+    /// The output of the lines of failed queries.
+    pub(crate) fn failure_output(&self) -> &LogOutput {
+        self.error_output.as_deref().unwrap_or(&self.output)
+    }
+
+    fn emit_to(
+        &self,
+        output: &LogOutput,
+        level: log::Level,
+        mode: DbLogMode,
+        start_time: crate::clock::Timestamp,
+        message: &str,
+        fields: Option<&LineFields<'_>>,
+    ) {
+        let timestamp = Some(start_time).filter(|_| self.show_timestamp);
+        let (admitted, dropped) = crate::throttle::admit(level);
+        if let Some(dropped) = dropped {
+            output.emit(log::Level::Warn, mode, timestamp, &dropped, None);
+        }
+        if !admitted {
+            return;
+        }
+        let message = match (self.show_backend, self.format, fields) {
+            (false, _, _) => Cow::Borrowed(message),
+            (true, LogFormat::Logfmt, Some(_)) => {
+                Cow::Owned(format!("{} backend={}", message, self.backend))
+            }
+            (true, _, _) => Cow::Owned(format!("[{}] {}", self.backend, message)),
+        };
+        output.emit(level, mode, timestamp, &message, fields);
+    }
+
+    /// Emits a line which is not about a single query.
+    pub(crate) fn emit_message(
+        &self,
+        level: log::Level,
+        mode: DbLogMode,
+        now: crate::clock::Timestamp,
+        message: &str,
+    ) {
+        self.emit(level, mode, now, message, None);
+    }
+
+    /// Whether the SQL of failed queries is recorded on the current `tracing` span.
+    pub(crate) fn records_failures_on_span(&self) -> bool {
+        #[cfg(feature = "tracing-events")]
+        return self.record_failed_query_on_span;
+        #[cfg(not(feature = "tracing-events"))]
+        false
+    }
+
+    /// The text of `query` as logged: with its literals and bind values
+    /// replaced by `?` if they are redacted.
+    pub(crate) fn loggable<'q>(&self, query: &'q str) -> Cow<'q, str> {
+        if self.redact_literals {
+            Cow::Owned(crate::normalize::normalize_sql(
+                query,
+                &NormalizationConfig::default(),
+            ))
+        } else {
+            Cow::Borrowed(query)
+        }
+    }
+
+    /// The text of `query` in the messages about it: as `loggable` returns
+    /// it, cut down to `max_query_bytes`.
+    pub(crate) fn quoted<'q>(&self, query: &'q str) -> Cow<'q, str> {
+        let query = self.loggable(query);
+        match self.max_query_bytes {
+            Some(max_bytes) if query.len() > max_bytes => {
+                Cow::Owned(crate::truncate::truncate_bytes(&query, max_bytes).into_owned())
+            }
+            _ => query,
+        }
+    }
+
+    /// Whether the query with the sequence number `seq` is logged although it
+    /// is not slow.
+    pub(crate) fn is_sampled(&self, seq: u64) -> bool {
+        match self.sample_fast_queries {
+            Some(one_in) if one_in > 1 => seq % u64::from(one_in) == 1,
+            _ => true,
+        }
+    }
+
+    /// Whether a query which took `duration` is close to the timeout budget.
+    pub(crate) fn is_near_timeout(&self, duration: Duration) -> bool {
+        matches!(self.timeout_budget, Some(budget) if duration >= budget.mul_f64(self.timeout_warn_fraction))
+    }
+}
+
+static GLOBAL_DEFAULTS: RwLock<Option<LoggingConfig>> = RwLock::new(None);
+
+/// Sets the configuration every `LoggingConnection` created from now on starts
+/// with, including the ones a pool establishes. Their setters still override it.
+/// Connections which already exist keep their configuration.
+pub fn set_global_defaults(config: LoggingConfig) {
+    *GLOBAL_DEFAULTS.write().unwrap_or_else(|e| e.into_inner()) = Some(config);
+}
+
+/// The configuration new connections to `B` start with: the global defaults
+/// if they were set, otherwise the preset `GST_DATABASE_LOGGING` names.
+pub(crate) fn defaults_for<B>() -> LoggingConfig {
+    let global = GLOBAL_DEFAULTS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let mut config = global.unwrap_or_else(LoggingConfig::from_env);
+    config.backend = crate::backend::backend_name::<B>();
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_connections_start_with_the_default_thresholds_whatever_their_backend() {
+        use diesel::sqlite::SqliteConnection;
+        use diesel::Connection;
+
+        let conn = crate::LoggingConnection::new(
+            SqliteConnection::establish(":memory:").unwrap(),
+            DbLogMode::Standard,
+        );
+        let thresholds = conn.config().thresholds.get(DbLogMode::Standard);
+        assert_eq!(thresholds, SlowQueryThresholds::default());
+        assert_eq!(conn.config().backend, "sqlite");
+    }
+
+    #[test]
+    fn the_development_preset_shows_every_query() {
+        let config = LoggingConfig::development();
+        assert_eq!(
+            config.thresholds.get(DbLogMode::Excessive).slow,
+            Duration::from_millis(50)
+        );
+        assert_eq!(config.levels.normal, log::Level::Info);
+        assert_eq!(config.format, LogFormat::Text);
+        assert!(config.show_seq && config.psql_ready && config.warn_on_zero_affected);
+    }
+
+    #[test]
+    fn the_production_preset_keeps_lines_small_redacted_and_parsable() {
+        let config = LoggingConfig::production();
+        assert_eq!(
+            config.thresholds.get(DbLogMode::Standard),
+            SlowQueryThresholds::default()
+        );
+        assert_eq!(config.levels.normal, log::Level::Trace);
+        assert_eq!(config.format, LogFormat::Logfmt);
+        assert!(config.log_query_id);
+        assert_eq!(config.max_query_bytes, Some(4096));
+        assert_eq!(
+            config.loggable("SELECT * FROM users WHERE name = 'alice' -- binds: [\"bob\"]"),
+            "SELECT * FROM users WHERE name = ?"
+        );
+        let sampled = (1..=200).filter(|&seq| config.is_sampled(seq)).count();
+        assert_eq!(sampled, 2);
+    }
+
+    #[test]
+    fn every_query_is_sampled_by_default() {
+        let config = LoggingConfig::default();
+        assert!((1..=10).all(|seq| config.is_sampled(seq)));
+        assert_eq!(config.loggable("SELECT 'a'"), "SELECT 'a'");
+    }
+}
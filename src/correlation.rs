@@ -0,0 +1,50 @@
+use std::cell::RefCell;
+
+thread_local! {
+    static CORRELATION_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Stamps every query logged on the current thread from now on with `id`,
+/// e.g. the ID of the request being handled, as `[req:<id>]`.
+///
+/// The ID is thread-local, so set it on the thread which runs the queries of the
+/// request and clear it with `clear_correlation_id` once the request is done.
+/// With the `tracing` feature, queries run inside a `tracing` span are
+/// stamped with the span if no ID is set.
+pub fn set_correlation_id(id: impl Into<String>) {
+    CORRELATION_ID.with(|current| *current.borrow_mut() = Some(id.into()));
+}
+
+/// Stops stamping queries logged on the current thread with a correlation ID.
+pub fn clear_correlation_id() {
+    CORRELATION_ID.with(|current| *current.borrow_mut() = None);
+}
+
+/// Returns the correlation ID of the current thread, if any.
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.with(|current| current.borrow().clone())
+}
+
+/// The correlation ID queries are stamped with: the one of the current thread,
+/// or with the `tracing` feature the current `tracing` span.
+///
+/// `tracing` does not expose the recorded field values of a span, so fields
+/// like `request_id` cannot be read. The span is identified by its name and
+/// ID instead, e.g. `http_request#42`, which correlates all queries run inside
+/// the same span. Without a current span no ID is used.
+pub(crate) fn effective_correlation_id() -> Option<String> {
+    correlation_id().or_else(span_correlation_id)
+}
+
+#[cfg(feature = "tracing")]
+fn span_correlation_id() -> Option<String> {
+    let span = tracing::Span::current();
+    let id = span.id()?;
+    let name = span.metadata().map_or("span", |metadata| metadata.name());
+    Some(format!("{}#{}", name, id.into_u64()))
+}
+
+#[cfg(not(feature = "tracing"))]
+fn span_correlation_id() -> Option<String> {
+    None
+}
@@ -0,0 +1,34 @@
+use diesel::result::{DatabaseErrorKind, Error};
+
+/// Messages of errors which mean that the connection itself is broken,
+/// as reported by libpq, the MySQL client library and SQLite.
+const DEAD_CONNECTION_MESSAGES: &[&str] = &[
+    "server closed the connection unexpectedly",
+    "terminating connection",
+    "no connection to the server",
+    "connection to server was lost",
+    "could not receive data from server",
+    "could not send data to server",
+    "ssl syscall error",
+    "connection reset by peer",
+    "broken pipe",
+    "server has gone away",
+    "lost connection to mysql server",
+    "database connection closed",
+];
+
+/// Whether `error` means that the connection is likely dead, e.g. because the
+/// database restarted or the pool handed out a broken connection, rather
+/// than that the query itself is wrong.
+pub(crate) fn is_dead_connection(error: &Error) -> bool {
+    match error {
+        Error::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _) => true,
+        Error::DatabaseError(_, info) => {
+            let message = info.message().to_ascii_lowercase();
+            DEAD_CONNECTION_MESSAGES
+                .iter()
+                .any(|dead| message.contains(dead))
+        }
+        _ => false,
+    }
+}
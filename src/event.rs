@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A query which was run through a `LoggingConnection`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct QueryEvent {
+    /// The SQL of the query, as rendered by `diesel::debug_query`.
+    pub query: String,
+    /// The kind of the query.
+    pub kind: crate::QueryKind,
+    /// Identifies the query across executions: the hash of its diesel `QueryId`
+    /// for statically typed queries, the fingerprint of its normalized SQL otherwise.
+    pub query_id: u64,
+    /// The sequence number of the query on its connection, starting at 1.
+    pub seq: u64,
+    /// The wall-clock time at which the query was started, from the time
+    /// source of the connection. It can jump, e.g. when the system clock is set.
+    pub start_time: std::time::SystemTime,
+    /// The monotonic time at which the query was started, to order events
+    /// against other `Instant`s of the process. `started + duration` is when
+    /// the query returned. It is meaningless outside of the process.
+    pub started: Instant,
+    /// How long the query took.
+    pub duration: Duration,
+    /// The number of rows returned or affected, if the query succeeded.
+    pub rows: Option<usize>,
+    /// Whether the query was considered slow.
+    pub slow: bool,
+    /// Whether the query is hand-written SQL, i.e. ran through `sql_query(..).load`,
+    /// `Connection::execute` or `batch_execute`, whether or not lines mark it.
+    pub raw: bool,
+}
+
+/// Sends `QueryEvent`s to a receiver owned by the user.
+#[derive(Default)]
+pub(crate) struct EventChannel {
+    sender: Mutex<Option<mpsc::Sender<QueryEvent>>>,
+}
+
+impl EventChannel {
+    pub(crate) fn open(&mut self) -> mpsc::Receiver<QueryEvent> {
+        let (sender, receiver) = mpsc::channel();
+        *self.sender() = Some(sender);
+        receiver
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.sender().is_some()
+    }
+
+    /// Sends the event, closing the channel if the receiver was dropped.
+    pub(crate) fn send(&self, event: QueryEvent) {
+        let mut sender = self.sender();
+        if let Some(s) = sender.as_ref() {
+            if s.send(event).is_err() {
+                *sender = None;
+            }
+        }
+    }
+
+    fn sender(&self) -> std::sync::MutexGuard<'_, Option<mpsc::Sender<QueryEvent>>> {
+        self.sender.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Keeps the most recent `QueryEvent`s until they are drained.
+#[derive(Default)]
+pub(crate) struct EventBuffer {
+    capacity: Option<usize>,
+    events: Mutex<VecDeque<QueryEvent>>,
+}
+
+impl EventBuffer {
+    pub(crate) fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        let events = self.events.get_mut().unwrap_or_else(|e| e.into_inner());
+        let keep = capacity.unwrap_or(0);
+        while events.len() > keep {
+            events.pop_front();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity.is_some()
+    }
+
+    /// Keeps the event, dropping the oldest one if the buffer is full.
+    pub(crate) fn push(&self, event: QueryEvent) {
+        let capacity = match self.capacity {
+            Some(capacity) if capacity > 0 => capacity,
+            _ => return,
+        };
+        let mut events = self.events();
+        if events.len() >= capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    pub(crate) fn drain(&self) -> Vec<QueryEvent> {
+        self.events().drain(..).collect()
+    }
+
+    fn events(&self) -> std::sync::MutexGuard<'_, VecDeque<QueryEvent>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sends the `QueryEvent`s of slow queries to a tokio channel, so async code
+/// can react to them. Without the `tokio` feature it is never open.
+#[derive(Default)]
+pub(crate) struct AsyncSlowQueries {
+    #[cfg(feature = "tokio")]
+    sender: Mutex<Option<tokio::sync::mpsc::UnboundedSender<QueryEvent>>>,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncSlowQueries {
+    pub(crate) fn set(&mut self, sender: Option<tokio::sync::mpsc::UnboundedSender<QueryEvent>>) {
+        *self.sender() = sender;
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.sender().is_some()
+    }
+
+    /// Sends the event without blocking, closing the channel if the receiver
+    /// was dropped.
+    pub(crate) fn send(&self, event: QueryEvent) {
+        let mut sender = self.sender();
+        if let Some(s) = sender.as_ref() {
+            if s.send(event).is_err() {
+                *sender = None;
+            }
+        }
+    }
+
+    fn sender(
+        &self,
+    ) -> std::sync::MutexGuard<'_, Option<tokio::sync::mpsc::UnboundedSender<QueryEvent>>> {
+        self.sender.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl AsyncSlowQueries {
+    pub(crate) fn is_open(&self) -> bool {
+        false
+    }
+
+    pub(crate) fn send(&self, _event: QueryEvent) {}
+}
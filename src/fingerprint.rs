@@ -0,0 +1,41 @@
+use std::any::TypeId;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Returns a hash identifying a query shape, as produced by `normalize_sql`,
+/// so that executions with the same shape end up in the same group.
+pub(crate) fn fingerprint(shape: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    shape.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns a hash identifying a statically typed query by its diesel `QueryId`.
+pub(crate) fn type_fingerprint(id: TypeId) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_shapes_have_equal_fingerprints() {
+        assert_eq!(fingerprint("SELECT ?"), fingerprint("SELECT ?"));
+        assert_ne!(fingerprint("SELECT ?"), fingerprint("SELECT ?, ?"));
+    }
+
+    #[test]
+    fn query_types_have_their_own_fingerprints() {
+        assert_eq!(
+            type_fingerprint(TypeId::of::<u8>()),
+            type_fingerprint(TypeId::of::<u8>())
+        );
+        assert_ne!(
+            type_fingerprint(TypeId::of::<u8>()),
+            type_fingerprint(TypeId::of::<u16>())
+        );
+    }
+}
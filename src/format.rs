@@ -0,0 +1,242 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::output::LineFields;
+
+/// How the line logged for a query is formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human readable, e.g. `Slow query ran in 1.20 s: SELECT ...`.
+    #[default]
+    Text,
+    /// `key=value` pairs, e.g. `duration_ms=12.3 rows=5 kind=select slow=false sql="SELECT ..."`.
+    /// The keys are the same as the key-values of the `kv` feature, plus `sql`.
+    Logfmt,
+}
+
+/// Formats the fields of a query as a logfmt line. `wait_ms`, `rows`, `batch_rows`, `statements`,
+/// `baseline_ms`, `test_tx` and `req` are only added if they apply.
+pub(crate) fn logfmt(
+    fields: &LineFields<'_>,
+    baseline: Option<Duration>,
+    test_transaction: bool,
+) -> String {
+    let mut line = format!("duration_ms={:.3}", fields.duration.as_secs_f64() * 1000.0);
+    if let Some(wait) = fields.wait {
+        let _ = write!(line, " wait_ms={:.3}", wait.as_secs_f64() * 1000.0);
+    }
+    if let Some(rows) = fields.rows {
+        let _ = write!(line, " rows={}", rows);
+    }
+    if let Some(batch_rows) = fields.batch_rows() {
+        let _ = write!(line, " batch_rows={}", batch_rows);
+    }
+    if let Some(statements) = fields.statements {
+        let _ = write!(line, " statements={}", statements);
+    }
+    let _ = write!(line, " kind={} slow={}", fields.kind.as_str(), fields.slow);
+    if let Some(baseline) = baseline {
+        let _ = write!(line, " baseline_ms={:.3}", baseline.as_secs_f64() * 1000.0);
+    }
+    if test_transaction {
+        line.push_str(" test_tx=true");
+    }
+    if let Some(id) = fields.correlation_id {
+        line.push_str(" req=");
+        push_quoted(&mut line, id);
+    }
+    line.push_str(" sql=");
+    push_quoted(&mut line, fields.query);
+    line
+}
+
+/// Appends the duration and row count of a query as stable tokens to a
+/// human readable line.
+pub(crate) fn append_machine_fields(line: &mut String, fields: &LineFields<'_>) {
+    let _ = write!(
+        line,
+        " dur_ms={:.1}",
+        fields.duration.as_secs_f64() * 1000.0
+    );
+    if let Some(rows) = fields.rows {
+        let _ = write!(line, " rows={}", rows);
+    }
+}
+
+/// Appends the columns a query returns, as `cols=[a,b]` to human readable
+/// lines and as `cols="a,b"` to logfmt lines.
+pub(crate) fn append_columns(line: &mut String, format: LogFormat, columns: &str) {
+    match format {
+        LogFormat::Text => {
+            let _ = write!(line, " cols=[{}]", columns);
+        }
+        LogFormat::Logfmt => {
+            line.push_str(" cols=");
+            push_quoted(line, columns);
+        }
+    }
+}
+
+/// Appends the estimated size of the rows a query returned, as `(~3.2 MB)`
+/// to human readable lines and as `result_bytes=3355443` to logfmt lines.
+pub(crate) fn append_result_bytes(line: &mut String, format: LogFormat, bytes: usize) {
+    match format {
+        LogFormat::Text => {
+            let _ = write!(line, " (~{})", format_bytes(bytes));
+        }
+        LogFormat::Logfmt => {
+            let _ = write!(line, " result_bytes={}", bytes);
+        }
+    }
+}
+
+/// Appends the number of bind parameters of a query as `params=2000`.
+pub(crate) fn append_param_count(line: &mut String, count: usize) {
+    let _ = write!(line, " params={}", count);
+}
+
+/// Renders a size in the most legible unit, e.g. `512 B` or `3.2 MB`.
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Adds the sequence number of a query, as a `#42` prefix to human readable
+/// lines and as `seq=42` to logfmt lines.
+pub(crate) fn add_seq(line: &mut String, format: LogFormat, seq: u64) {
+    match format {
+        LogFormat::Text => line.insert_str(0, &format!("#{} ", seq)),
+        LogFormat::Logfmt => {
+            let _ = write!(line, " seq={}", seq);
+        }
+    }
+}
+
+/// The wording of slow query lines, as long as no template is set.
+pub const DEFAULT_SLOW_QUERY_TEMPLATE: &str = "Slow query ran in {duration}: {query}";
+
+/// Fills the placeholders of a slow query template. The query is filled in
+/// last, so placeholders in its SQL are left alone.
+pub(crate) fn fill_template(
+    template: &str,
+    duration: &str,
+    rows: Option<usize>,
+    timestamp: crate::clock::Timestamp,
+    query: &str,
+) -> String {
+    let rows = rows.map_or_else(|| String::from("?"), |rows| rows.to_string());
+    template
+        .replace("{duration}", duration)
+        .replace("{rows}", &rows)
+        .replace("{timestamp}", &timestamp.to_string())
+        .replace("{query}", query)
+}
+
+/// Adds the estimated cost of a query, as a `[cost=1234.50]` prefix to human
+/// readable lines and as `cost=1234.50` to logfmt lines.
+pub(crate) fn add_cost(line: &mut String, format: LogFormat, cost: f64) {
+    match format {
+        LogFormat::Text => line.insert_str(0, &format!("[cost={:.2}] ", cost)),
+        LogFormat::Logfmt => {
+            let _ = write!(line, " cost={:.2}", cost);
+        }
+    }
+}
+
+/// Marks the line of hand-written SQL, with a `[raw]` prefix in human
+/// readable lines and as `raw=true` in logfmt lines.
+pub(crate) fn mark_raw(line: &mut String, format: LogFormat) {
+    match format {
+        LogFormat::Text => line.insert_str(0, "[raw] "),
+        LogFormat::Logfmt => line.push_str(" raw=true"),
+    }
+}
+
+/// Appends the constant context of a connection as `key=value` pairs,
+/// with quoted values in logfmt lines.
+pub(crate) fn append_context(line: &mut String, format: LogFormat, context: &[(String, String)]) {
+    for (key, value) in context {
+        let _ = write!(line, " {}=", key);
+        match format {
+            LogFormat::Text => line.push_str(value),
+            LogFormat::Logfmt => push_quoted(line, value),
+        }
+    }
+}
+
+/// Appends `value` in double quotes, escaping quotes, backslashes and line breaks.
+fn push_quoted(line: &mut String, value: &str) {
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QueryKind;
+
+    fn fields(query: &str) -> LineFields<'_> {
+        LineFields {
+            query,
+            kind: QueryKind::classify(query),
+            duration: Duration::from_micros(12_345),
+            wait: None,
+            rows: None,
+            slow: false,
+            raw: false,
+            statements: None,
+            correlation_id: None,
+        }
+    }
+
+    #[test]
+    fn logfmt_leaves_out_what_does_not_apply() {
+        assert_eq!(
+            logfmt(&fields("SELECT 1"), None, false),
+            "duration_ms=12.345 kind=select slow=false sql=\"SELECT 1\""
+        );
+    }
+
+    #[test]
+    fn logfmt_adds_every_field_which_applies() {
+        let fields = LineFields {
+            wait: Some(Duration::from_millis(2)),
+            rows: Some(3),
+            slow: true,
+            correlation_id: Some("req 1"),
+            ..fields("SELECT 1")
+        };
+        assert_eq!(
+            logfmt(&fields, Some(Duration::from_millis(5)), true),
+            "duration_ms=12.345 wait_ms=2.000 rows=3 kind=select slow=true baseline_ms=5.000 \
+             test_tx=true req=\"req 1\" sql=\"SELECT 1\""
+        );
+    }
+
+    #[test]
+    fn logfmt_values_are_quoted_and_escaped() {
+        let query = "SELECT \"a\\b\"\n\tFROM t";
+        assert!(
+            logfmt(&fields(query), None, false).ends_with(r#" sql="SELECT \"a\\b\"\n\tFROM t""#)
+        );
+    }
+}
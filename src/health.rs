@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The health of the queries a `LoggingConnection` ran in the recent window,
+/// see `LoggingConnection::health_summary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HealthSummary {
+    /// The length of the window the summary covers.
+    pub window: Duration,
+    /// The number of queries run in the window, failed ones included.
+    pub queries: usize,
+    /// The number of queries which failed.
+    pub errors: usize,
+    /// The number of queries which were slow by the fixed threshold.
+    pub slow_queries: usize,
+    /// The 95th percentile of the query durations, if any query ran.
+    pub p95: Option<Duration>,
+}
+
+/// One query of the window.
+struct Sample {
+    finished: Instant,
+    duration: Duration,
+    failed: bool,
+    slow: bool,
+}
+
+/// The queries of the recent window, if tracking is enabled.
+#[derive(Default)]
+pub(crate) struct HealthWindow {
+    window: Option<Duration>,
+    samples: Mutex<VecDeque<Sample>>,
+}
+
+impl HealthWindow {
+    pub(crate) fn set_window(&mut self, window: Option<Duration>) {
+        self.window = window;
+        if window.is_none() {
+            self.samples().clear();
+        }
+    }
+
+    pub(crate) fn record(&self, duration: Duration, failed: bool, slow: bool) {
+        let window = match self.window {
+            Some(window) => window,
+            None => return,
+        };
+        let now = Instant::now();
+        let mut samples = self.samples();
+        prune(&mut samples, now, window);
+        samples.push_back(Sample {
+            finished: now,
+            duration,
+            failed,
+            slow,
+        });
+    }
+
+    /// Summarizes the window without resetting it, or returns `None` if
+    /// tracking is disabled.
+    pub(crate) fn summary(&self) -> Option<HealthSummary> {
+        let window = self.window?;
+        let mut samples = self.samples();
+        prune(&mut samples, Instant::now(), window);
+        let mut durations = samples.iter().map(|s| s.duration).collect::<Vec<_>>();
+        durations.sort_unstable();
+        let p95 = match durations.len() {
+            0 => None,
+            // The nearest rank, i.e. the element at `ceil(0.95 * len)`.
+            len => Some(durations[(len * 95 - 1) / 100]),
+        };
+        Some(HealthSummary {
+            window,
+            queries: samples.len(),
+            errors: samples.iter().filter(|s| s.failed).count(),
+            slow_queries: samples.iter().filter(|s| s.slow).count(),
+            p95,
+        })
+    }
+
+    fn samples(&self) -> std::sync::MutexGuard<'_, VecDeque<Sample>> {
+        self.samples.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Drops the samples which finished before the window.
+fn prune(samples: &mut VecDeque<Sample>, now: Instant, window: Duration) {
+    while matches!(samples.front(), Some(s) if now.duration_since(s.finished) > window) {
+        samples.pop_front();
+    }
+}
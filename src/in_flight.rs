@@ -0,0 +1,48 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+use crate::config::LoggingConfig;
+use crate::{clock, DbLogMode};
+
+/// Logs the query in flight at `error` if the thread panics while running it,
+/// e.g. in the serialization of a custom type. The query is only rendered if
+/// that happens.
+pub(crate) struct InFlight<'c, 'q, F: FnOnce() -> Cow<'q, str>> {
+    config: &'c LoggingConfig,
+    mode: DbLogMode,
+    render: Option<F>,
+    query: PhantomData<Cow<'q, str>>,
+}
+
+impl<'c, 'q, F: FnOnce() -> Cow<'q, str>> InFlight<'c, 'q, F> {
+    pub(crate) fn new(config: &'c LoggingConfig, mode: DbLogMode, render: F) -> Self {
+        InFlight {
+            config,
+            mode,
+            render: Some(render),
+            query: PhantomData,
+        }
+    }
+
+    /// The query returned, so gives back the renderer for logging it.
+    pub(crate) fn finish(mut self) -> F {
+        self.render
+            .take()
+            .expect("the renderer is only taken by `finish`")
+    }
+}
+
+impl<'q, F: FnOnce() -> Cow<'q, str>> Drop for InFlight<'_, 'q, F> {
+    fn drop(&mut self) {
+        let render = match self.render.take() {
+            Some(render) if std::thread::panicking() && !self.mode.do_not_log() => render,
+            _ => return,
+        };
+        self.config.emit_message(
+            log::Level::Error,
+            self.mode,
+            clock::now(&*self.config.clock),
+            &format!("Panicked while running query: {}", render()),
+        );
+    }
+}
@@ -0,0 +1,131 @@
+/// The kind of statement a query is, derived from its leading keyword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryKind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    /// Schema changes like `CREATE`, `ALTER` or `DROP`.
+    Ddl,
+    /// Several statements run together with `batch_execute`.
+    Batch,
+    /// `COPY` between a table and a file or program on the database server.
+    ///
+    /// Diesel has no API for `COPY ... FROM STDIN` or `TO STDOUT`, so only
+    /// copies which run as a plain statement through `execute` are seen.
+    Copy,
+    Other,
+}
+
+impl QueryKind {
+    /// Classifies a query by its first keyword, skipping leading comments
+    /// and parentheses. `WITH` queries are treated as selects.
+    pub fn classify(query: &str) -> Self {
+        let keyword = first_keyword(query);
+        let is = |k: &str| keyword.eq_ignore_ascii_case(k);
+        if is("SELECT") || is("WITH") || is("VALUES") || is("SHOW") || is("EXPLAIN") {
+            QueryKind::Select
+        } else if is("INSERT") || is("REPLACE") {
+            QueryKind::Insert
+        } else if is("UPDATE") {
+            QueryKind::Update
+        } else if is("DELETE") {
+            QueryKind::Delete
+        } else if ["CREATE", "ALTER", "DROP", "TRUNCATE", "RENAME", "COMMENT"]
+            .iter()
+            .any(|k| is(k))
+        {
+            QueryKind::Ddl
+        } else if is("COPY") {
+            QueryKind::Copy
+        } else {
+            QueryKind::Other
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QueryKind::Select => "select",
+            QueryKind::Insert => "insert",
+            QueryKind::Update => "update",
+            QueryKind::Delete => "delete",
+            QueryKind::Ddl => "ddl",
+            QueryKind::Batch => "batch",
+            QueryKind::Copy => "copy",
+            QueryKind::Other => "other",
+        }
+    }
+
+    /// The `log` target lines about queries of this kind are logged under,
+    /// e.g. to enable writes with `RUST_LOG=diesel_logger::write=debug`.
+    #[cfg(not(feature = "tracing-events"))]
+    pub(crate) fn log_target(self) -> &'static str {
+        match self {
+            QueryKind::Select => "diesel_logger::select",
+            QueryKind::Insert | QueryKind::Update | QueryKind::Delete | QueryKind::Copy => {
+                "diesel_logger::write"
+            }
+            QueryKind::Ddl => "diesel_logger::ddl",
+            QueryKind::Batch | QueryKind::Other => "diesel_logger::other",
+        }
+    }
+}
+
+/// Every target of `QueryKind::log_target`.
+pub(crate) const LOG_TARGETS: [&str; 4] = [
+    "diesel_logger::select",
+    "diesel_logger::write",
+    "diesel_logger::ddl",
+    "diesel_logger::other",
+];
+
+fn first_keyword(mut query: &str) -> &str {
+    loop {
+        query = query.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+        if query.starts_with("--") {
+            query = query.find('\n').map_or("", |end| &query[end..]);
+        } else if query.starts_with("/*") {
+            query = query.find("*/").map_or("", |end| &query[end + 2..]);
+        } else {
+            break;
+        }
+    }
+    let end = query
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(query.len());
+    &query[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_classified_by_their_first_keyword() {
+        let cases = [
+            ("SELECT 1", QueryKind::Select),
+            ("with x AS (SELECT 1) DELETE FROM t", QueryKind::Select),
+            ("(SELECT 1) UNION (SELECT 2)", QueryKind::Select),
+            ("insert INTO t VALUES (1)", QueryKind::Insert),
+            ("UPDATE t SET a = 1", QueryKind::Update),
+            ("DELETE FROM t", QueryKind::Delete),
+            ("CREATE TABLE t (a INTEGER)", QueryKind::Ddl),
+            ("truncate t", QueryKind::Ddl),
+            ("COPY t TO '/tmp/t.csv'", QueryKind::Copy),
+            ("BEGIN", QueryKind::Other),
+            ("", QueryKind::Other),
+        ];
+        for (query, kind) in cases {
+            assert_eq!(QueryKind::classify(query), kind, "{}", query);
+        }
+    }
+
+    #[test]
+    fn leading_comments_are_skipped() {
+        assert_eq!(
+            QueryKind::classify("-- load the users\n/* hint */ SELECT * FROM users"),
+            QueryKind::Select
+        );
+        assert_eq!(QueryKind::classify("-- only a comment"), QueryKind::Other);
+    }
+}
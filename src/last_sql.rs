@@ -0,0 +1,27 @@
+use std::cell::RefCell;
+
+thread_local! {
+    static LAST_SQL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Returns the SQL of the last query run on the current thread through a
+/// `LoggingConnection` with `set_track_last_sql` enabled, e.g. to report it
+/// from a panic hook or after `catch_unwind`.
+///
+/// The SQL is stored per thread, not per connection: it is the last query of
+/// any tracking connection used on this thread, and queries run on other
+/// threads are not visible here. It is stored before the query runs, so it is
+/// also available if the query itself panics.
+pub fn last_executed_sql() -> Option<String> {
+    LAST_SQL.with(|sql| sql.borrow().clone())
+}
+
+pub(crate) fn set(query: &str) {
+    LAST_SQL.with(|sql| {
+        // Reuse the allocation, this runs for every query.
+        let mut sql = sql.borrow_mut();
+        let sql = sql.get_or_insert_with(String::new);
+        sql.clear();
+        sql.push_str(query);
+    });
+}
@@ -4,10 +4,114 @@ use diesel::deserialize::QueryableByName;
 use diesel::prelude::*;
 use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
 use diesel::sql_types::HasSqlType;
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
+#[macro_use]
+mod macros;
+
+mod adaptive;
+mod aggregate;
+mod backend;
+mod batch;
+mod binds;
+mod capture;
+mod clock;
+mod columns;
+mod comments;
+mod config;
+mod correlation;
+mod dead_connection;
+mod event;
+mod fingerprint;
+mod format;
+mod health;
+mod in_flight;
+mod kind;
+mod last_sql;
+mod lock_error;
+mod missing_where;
+mod normalize;
+mod one_shot;
+mod output;
+mod pool;
+#[cfg(feature = "postgres")]
+mod postgres;
+pub mod prelude;
+mod prepared;
+mod psql;
+mod query_sink;
+mod ratelimit;
+mod redact;
+mod scope;
+#[cfg(feature = "test-util")]
+mod sink;
+mod slowest;
+mod thresholds;
+mod throttle;
+mod totals;
+mod transaction;
+mod truncate;
+mod worst;
+
+pub use crate::adaptive::{AdaptiveThresholds, MovingAverageThreshold};
+pub use crate::aggregate::QueryAggregate;
+pub use crate::clock::{SystemClock, TimeSource};
+pub use crate::config::{set_global_defaults, LoggingConfig};
+pub use crate::correlation::{clear_correlation_id, correlation_id, set_correlation_id};
+pub use crate::event::QueryEvent;
+pub use crate::format::{LogFormat, DEFAULT_SLOW_QUERY_TEMPLATE};
+pub use crate::health::HealthSummary;
+pub use crate::kind::QueryKind;
+pub use crate::last_sql::last_executed_sql;
+pub use crate::normalize::{normalize_sql, NormalizationConfig};
+pub use crate::one_shot::{expect_rows_from_next_query, log_next_query_as, suppress_next_query};
+pub use crate::output::LogOutput;
+pub use crate::pool::LoggingConnectionManager;
+pub use crate::query_sink::{CallbackSink, FileSink, LogSink, QuerySink};
+pub use crate::ratelimit::SlowQueryRateLimit;
+#[cfg(feature = "test-util")]
+pub use crate::sink::{CaptureSink, CapturedLines, FixedClock};
+pub use crate::slowest::SlowQueryRecord;
+pub use crate::thresholds::{LiveThresholds, LogLevels, SlowQueryThresholds};
+pub use crate::throttle::{set_max_logs_per_sec, DEFAULT_MAX_LOGS_PER_SEC};
+
+use crate::adaptive::AdaptiveBaselines;
+use crate::aggregate::QueryAggregates;
+use crate::event::{AsyncSlowQueries, EventBuffer, EventChannel};
+use crate::health::HealthWindow;
+use crate::in_flight::InFlight;
+use crate::output::LineFields;
+use crate::ratelimit::SlowQueryLimiter;
+use crate::slowest::SlowestQuery;
+use crate::totals::{Access, TotalTime};
+use crate::transaction::{
+    TransactionBuffer, TransactionQueryCounter, TransactionSummaries, TransactionTimer,
+};
+use crate::truncate::{truncate_bytes, truncate_chars};
+
+/// A callback for slow queries, see `LoggingConnection::set_on_slow_query`.
+pub type SlowQueryCallback = Arc<dyn Fn(&QueryEvent) + Send + Sync>;
+
+/// Decides whether a query is slow, see `LoggingConnection::set_slow_classifier`.
+pub type SlowQueryClassifier = Arc<dyn Fn(&QueryEvent) -> bool + Send + Sync>;
+
+/// A callback for the duration of commits, see `LoggingConnection::set_on_commit`.
+pub type CommitCallback = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// Renders the errors of failed queries, see `LoggingConnection::set_error_formatter`.
+pub type ErrorFormatter = Arc<dyn Fn(&diesel::result::Error) -> String + Send + Sync>;
+
+/// Decides from its SQL whether a query is logged, see `LoggingConnection::set_log_predicate`.
+pub type LogPredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
 /// A log mode which determines the type of logging connection is established.
+/// New modes may be added, so matches on it need a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
 pub enum DbLogMode {
     /// Do not log.
     NoLog,
@@ -18,62 +122,1934 @@ pub enum DbLogMode {
     /// Log everything all the time.
     Excessive,
     /// Log everything all the time, but shorten the records so we print only the start of a query.
+    /// Like `set_max_query_bytes`, this only shortens the logged text.
     ExcessiveMini,
+    /// Log nothing but failed queries, with their SQL, duration and error at `error`.
+    /// Successful queries are not rendered, except for `load` and friends,
+    /// which consume their query while running it.
+    OnError,
+    /// Log nothing, but still time every query for the statistics, like
+    /// `slowest_query`, aggregates and slow query callbacks.
+    Silent,
+    /// Log one machine readable line per query at `info`, for building query
+    /// profiles offline. The lines are logfmt with exactly these fields, in
+    /// this order:
+    ///
+    /// - `fp`: the fingerprint of the normalized SQL, 16 hex digits
+    /// - `qid`: the hash of the diesel `QueryId` of statically typed queries,
+    ///   otherwise the same as `fp`, 16 hex digits
+    /// - `kind`: the `QueryKind`, e.g. `select`
+    /// - `duration_us`: the duration in whole microseconds
+    /// - `rows`: the number of rows returned or affected, left out if unknown
+    ///
+    /// e.g. `fp=4f2c0d9ab1e3a7c5 qid=4f2c0d9ab1e3a7c5 kind=select duration_us=1250 rows=3`.
+    /// The SQL is not logged. Failed queries are logged like in `OnError` mode.
+    Profile,
 }
 
-impl DbLogMode {
-    pub fn from_env() -> Self {
-        if let Ok(mode) = ::std::env::var("GST_DATABASE_LOGGING") {
-            let mode = mode.to_lowercase();
-            match mode.as_str() {
-                "standard" => DbLogMode::Standard,
-                "verbose" => DbLogMode::Verbose,
-                "excessive" => DbLogMode::Excessive,
-                "excessive-mini" => DbLogMode::ExcessiveMini,
-                _ => DbLogMode::NoLog,
+impl DbLogMode {
+    pub fn from_env() -> Self {
+        if let Ok(mode) = ::std::env::var("GST_DATABASE_LOGGING") {
+            let mode = mode.to_lowercase();
+            match mode.as_str() {
+                "standard" => DbLogMode::Standard,
+                "verbose" => DbLogMode::Verbose,
+                "excessive" => DbLogMode::Excessive,
+                "excessive-mini" => DbLogMode::ExcessiveMini,
+                "on-error" => DbLogMode::OnError,
+                "silent" => DbLogMode::Silent,
+                "profile" => DbLogMode::Profile,
+                // New connections adopt the preset, see `LoggingConfig::from_env`.
+                "dev" | "development" | "prod" | "production" => DbLogMode::Standard,
+                _ => DbLogMode::NoLog,
+            }
+        } else {
+            DbLogMode::NoLog
+        }
+    }
+
+    pub fn do_not_log(self) -> bool {
+        self == DbLogMode::NoLog || self == DbLogMode::Silent
+    }
+
+    /// The level a successful query which took `duration` is logged at in this
+    /// mode, or `None` if the mode does not log it. Connections refine it:
+    /// queries far above the threshold of `warn` are escalated to `error`,
+    /// queries above their adaptive baseline are logged at least at `info`
+    /// and fast queries in `Standard` mode at the configured fast query level.
+    pub fn should_emit(
+        self,
+        duration: Duration,
+        thresholds: &SlowQueryThresholds,
+    ) -> Option<log::Level> {
+        use log::Level;
+
+        match self {
+            DbLogMode::Standard if thresholds.is_very_slow(duration) => Some(Level::Warn),
+            DbLogMode::Standard if thresholds.is_slow(duration) => Some(Level::Info),
+            DbLogMode::Standard => Some(Level::Debug),
+            DbLogMode::Verbose => Some(Level::Warn),
+            DbLogMode::Excessive | DbLogMode::ExcessiveMini if thresholds.is_slow(duration) => {
+                Some(Level::Warn)
+            }
+            DbLogMode::Excessive | DbLogMode::ExcessiveMini => Some(Level::Debug),
+            DbLogMode::Profile => Some(Level::Info),
+            DbLogMode::NoLog | DbLogMode::OnError | DbLogMode::Silent => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            DbLogMode::NoLog => 0,
+            DbLogMode::Standard => 1,
+            DbLogMode::Verbose => 2,
+            DbLogMode::Excessive => 3,
+            DbLogMode::ExcessiveMini => 4,
+            DbLogMode::OnError => 5,
+            DbLogMode::Silent => 6,
+            DbLogMode::Profile => 7,
+        }
+    }
+
+    fn from_u8(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(DbLogMode::NoLog),
+            1 => Some(DbLogMode::Standard),
+            2 => Some(DbLogMode::Verbose),
+            3 => Some(DbLogMode::Excessive),
+            4 => Some(DbLogMode::ExcessiveMini),
+            5 => Some(DbLogMode::OnError),
+            6 => Some(DbLogMode::Silent),
+            7 => Some(DbLogMode::Profile),
+            _ => None,
+        }
+    }
+}
+
+/// Marks the global log mode as not yet seeded from the environment.
+const GLOBAL_LOG_MODE_UNSET: u8 = u8::MAX;
+
+/// Marks that the next query of a connection runs in the mode of the connection.
+const NO_NEXT_LOG_MODE: u8 = u8::MAX;
+
+static GLOBAL_LOG_MODE: AtomicU8 = AtomicU8::new(GLOBAL_LOG_MODE_UNSET);
+
+/// Sets the log mode used by every `LoggingConnection` established from now on.
+/// Connections which already exist keep their mode.
+pub fn set_global_log_mode(mode: DbLogMode) {
+    GLOBAL_LOG_MODE.store(mode.to_u8(), Ordering::Relaxed);
+}
+
+/// Returns the log mode newly established connections use.
+/// Until `set_global_log_mode` is called, this is `DbLogMode::from_env()`.
+pub fn global_log_mode() -> DbLogMode {
+    if let Some(mode) = DbLogMode::from_u8(GLOBAL_LOG_MODE.load(Ordering::Relaxed)) {
+        return mode;
+    }
+    let mode = DbLogMode::from_env();
+    // Somebody else may have set the mode in the meantime, theirs wins.
+    match GLOBAL_LOG_MODE.compare_exchange(
+        GLOBAL_LOG_MODE_UNSET,
+        mode.to_u8(),
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => mode,
+        Err(current) => DbLogMode::from_u8(current).unwrap_or(mode),
+    }
+}
+
+/// Wraps a diesel `Connection` to time and log each query using
+/// the configured logger for the `log` crate.
+///
+/// Currently, this produces a `debug` log on every query, an `info` on slow
+/// queries and a `warn`ing on very slow queries, which becomes an `error` from
+/// five times the very slow threshold on. The thresholds are 1 and 5 seconds,
+/// see `SlowQueryThresholds` for presets. They can be changed per log mode
+/// with `set_slow_query_thresholds`, or for all modes at once with
+/// `set_default_slow_query_thresholds`.
+///
+/// # Supported connections
+///
+/// `C` has to be a concrete, sized diesel connection which is `Send + 'static`,
+/// e.g. `PgConnection` or `SqliteConnection`. Diesel's `Connection` trait has
+/// generic methods and requires `Sized`, so it is not object safe and a
+/// `Box<dyn Connection<...>>` cannot exist. If a framework needs to erase the
+/// connection type, wrap the concrete connection first and box the result:
+///
+/// ```
+/// # use diesel::prelude::*;
+/// # use diesel::sqlite::SqliteConnection;
+/// # use diesel_logger::{DbLogMode, LoggingConnection};
+/// let conn = SqliteConnection::establish(":memory:").unwrap();
+/// let conn: Box<LoggingConnection<SqliteConnection>> =
+///     Box::new(LoggingConnection::new(conn, DbLogMode::Standard));
+/// conn.execute("SELECT 1").unwrap();
+/// // Down to `Any`, and back once the type is known.
+/// let erased: Box<dyn std::any::Any> = conn;
+/// assert!(erased.downcast_ref::<LoggingConnection<SqliteConnection>>().is_some());
+/// ```
+///
+/// Boxing the connection itself does not compile:
+///
+/// ```compile_fail
+/// # use diesel::connection::AnsiTransactionManager;
+/// # use diesel::sqlite::Sqlite;
+/// # use diesel_logger::LoggingConnection;
+/// type Boxed =
+///     Box<dyn diesel::Connection<Backend = Sqlite, TransactionManager = AnsiTransactionManager>>;
+/// fn wrap(conn: Boxed) -> LoggingConnection<Boxed> {
+///     unimplemented!()
+/// }
+/// ```
+///
+/// A pooled connection like `PooledConnection<ConnectionManager<PgConnection>>`
+/// only derefs to its connection and cannot be wrapped after the fact. Instead,
+/// let the pool manage logging connections, i.e. use
+/// `Pool<ConnectionManager<LoggingConnection<PgConnection>>>`: the manager
+/// creates them with `establish` and checks them with `R2D2Connection::ping`.
+/// They can be configured with a `CustomizeConnection` when acquired.
+/// To keep an existing manager, wrap it in a `LoggingConnectionManager`.
+///
+/// # Threads
+///
+/// A `LoggingConnection` is `Send` and `Sync` exactly if its inner connection
+/// is. The logging state itself is always `Sync`: statistics and buffers are
+/// atomics or behind locks, and the mode and configuration only change through
+/// `&mut self`. Sharing a connection between threads never races on it, and
+/// the logging layer adds no requirement beyond those of the inner connection.
+pub struct LoggingConnection<C: Connection> {
+    pub conn: C,
+    pub log_mode: DbLogMode,
+    config: LoggingConfig,
+    adaptive: AdaptiveBaselines,
+    aggregates: QueryAggregates,
+    last_query_was_slow: AtomicBool,
+    /// How long the last query ran in nanoseconds, as measured by `run_and_log`.
+    last_query_nanos: AtomicU64,
+    slowest_query: SlowestQuery,
+    worst_queries: worst::WorstQueries,
+    in_test_transaction: AtomicBool,
+    events: EventChannel,
+    event_buffer: EventBuffer,
+    sinks: Vec<Arc<dyn QuerySink>>,
+    on_slow_query: Option<SlowQueryCallback>,
+    slow_classifier: Option<SlowQueryClassifier>,
+    async_slow_queries: AsyncSlowQueries,
+    on_commit: Option<CommitCallback>,
+    error_formatter: Option<ErrorFormatter>,
+    log_predicate: Option<LogPredicate>,
+    track_last_sql: bool,
+    track_logging_overhead: bool,
+    /// Explains a very slow query, set by backend modules.
+    /// Returns the message to log, if the query could be explained.
+    explain: Option<fn(&C, &str) -> Option<String>>,
+    /// Estimates the cost of a query without running it, set by backend modules.
+    estimate_cost: Option<fn(&C, &str) -> Option<f64>>,
+    prepared_statements: prepared::PreparedStatements,
+    slow_query_limiter: SlowQueryLimiter,
+    transaction_buffer: TransactionBuffer,
+    capture: capture::QueryCapture,
+    migrations: scope::Scope,
+    without_logging: scope::Scope,
+    total_time: TotalTime,
+    health: HealthWindow,
+    transaction_queries: TransactionQueryCounter,
+    transaction_timer: TransactionTimer,
+    transaction_summaries: TransactionSummaries,
+    /// The time the next query waited for this connection, in nanoseconds.
+    pool_wait: AtomicU64,
+    /// The estimated size of the rows the current query returned, in bytes.
+    result_bytes: AtomicUsize,
+    /// The number of queries run so far.
+    query_seq: AtomicU64,
+    /// The mode the next query is logged in, if set by `log_next`.
+    next_log_mode: AtomicU8,
+    /// The time spent logging around queries, in nanoseconds, if tracked.
+    logging_overhead: AtomicU64,
+}
+
+impl<C: Connection> LoggingConnection<C> {
+    pub fn new(conn: C, log_mode: DbLogMode) -> Self {
+        LoggingConnection {
+            conn,
+            log_mode,
+            config: config::defaults_for::<C::Backend>(),
+            adaptive: AdaptiveBaselines::default(),
+            aggregates: QueryAggregates::default(),
+            last_query_was_slow: AtomicBool::new(false),
+            last_query_nanos: AtomicU64::new(0),
+            slowest_query: SlowestQuery::default(),
+            worst_queries: worst::WorstQueries::default(),
+            in_test_transaction: AtomicBool::new(false),
+            events: EventChannel::default(),
+            event_buffer: EventBuffer::default(),
+            sinks: Vec::new(),
+            on_slow_query: None,
+            slow_classifier: None,
+            async_slow_queries: AsyncSlowQueries::default(),
+            on_commit: None,
+            error_formatter: None,
+            log_predicate: None,
+            track_last_sql: false,
+            track_logging_overhead: false,
+            explain: None,
+            estimate_cost: None,
+            prepared_statements: prepared::PreparedStatements::default(),
+            slow_query_limiter: SlowQueryLimiter::default(),
+            transaction_buffer: TransactionBuffer::default(),
+            capture: capture::QueryCapture::default(),
+            migrations: scope::Scope::default(),
+            without_logging: scope::Scope::default(),
+            total_time: TotalTime::default(),
+            health: HealthWindow::default(),
+            transaction_queries: TransactionQueryCounter::default(),
+            transaction_timer: TransactionTimer::default(),
+            transaction_summaries: TransactionSummaries::default(),
+            pool_wait: AtomicU64::new(0),
+            result_bytes: AtomicUsize::new(0),
+            query_seq: AtomicU64::new(0),
+            next_log_mode: AtomicU8::new(NO_NEXT_LOG_MODE),
+            logging_overhead: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the wrapped connection, e.g. for backend specific APIs.
+    /// Queries run on it directly are not logged.
+    pub fn inner(&self) -> &C {
+        &self.conn
+    }
+
+    /// The logging configuration of this connection, e.g. to pass it to
+    /// `set_global_defaults` after configuring one connection.
+    pub fn config(&self) -> &LoggingConfig {
+        &self.config
+    }
+
+    /// Replaces the logging configuration of this connection, e.g. with
+    /// `LoggingConfig::production()` or the `config()` of another connection.
+    /// The backend of this connection is kept.
+    pub fn set_config(&mut self, config: LoggingConfig) {
+        self.config = LoggingConfig {
+            backend: self.config.backend,
+            ..config
+        };
+    }
+
+    /// Changes the mode the following queries are logged in.
+    pub fn set_log_mode(&mut self, log_mode: DbLogMode) {
+        self.log_mode = log_mode;
+    }
+
+    /// Sets the log mode like `set_log_mode` and returns the previous one, like
+    /// `std::mem::replace`, e.g. to restore it after a temporary change.
+    pub fn replace_log_mode(&mut self, log_mode: DbLogMode) -> DbLogMode {
+        std::mem::replace(&mut self.log_mode, log_mode)
+    }
+
+    /// Logs only the very next query run on this connection in `mode`, e.g.
+    /// `conn.log_next(DbLogMode::Excessive)` before a query builder chain to
+    /// inspect it on an otherwise quiet connection. Later queries use the mode
+    /// of the connection again.
+    ///
+    /// It applies to whichever query runs next on this connection, also if
+    /// another thread sharing the connection runs it. It takes precedence over
+    /// `log_next_query_as`, which the same query consumes as well.
+    pub fn log_next(&self, mode: DbLogMode) {
+        self.next_log_mode.store(mode.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Logs a short summary like `<bulk insert, 48 KB SQL, 2000 rows>` instead of
+    /// queries whose SQL is longer than `max_bytes` bytes, e.g. huge `IN` lists or
+    /// bulk inserts, so logging them does not become a problem itself.
+    ///
+    /// Pass `None` to log queries in full again, which is the default.
+    pub fn set_summarize_query_bytes(&mut self, max_bytes: Option<usize>) {
+        self.config.summarize_query_bytes = max_bytes;
+    }
+
+    /// Cuts logged queries down to at most `max_bytes` bytes, appending `…`
+    /// when something was cut off. The cut never splits a UTF-8 character.
+    /// Only the logged text is cut: query IDs, aggregates, rate limits and
+    /// events still use the full query, so queries which only differ after
+    /// the cut are told apart.
+    ///
+    /// Pass `None` to log queries in full again.
+    pub fn set_max_query_bytes(&mut self, max_bytes: Option<usize>) {
+        self.config.max_query_bytes = max_bytes;
+    }
+
+    /// Shortens bind values whose `Debug` output is longer than `max_bytes`,
+    /// e.g. large `bytea` or `BLOB` values, while keeping the SQL intact.
+    /// Byte arrays are logged as `<N bytes>`, strings are cut and end with `…`.
+    ///
+    /// Pass `None` to log bind values in full again.
+    pub fn set_max_bind_bytes(&mut self, max_bytes: Option<usize>) {
+        self.config.max_bind_bytes = max_bytes;
+    }
+
+    /// Sets the level used for queries below the slow query threshold in
+    /// `Standard` mode, `debug` by default. Use `trace` for very chatty services.
+    /// Slow queries are still escalated to `info` and `warn`.
+    pub fn set_fast_query_level(&mut self, level: log::Level) {
+        self.config.levels.normal = level;
+    }
+
+    /// Sets the levels used in `Standard` mode for fast, slow and very slow
+    /// queries, e.g. `error` for very slow queries so they page someone.
+    pub fn set_log_levels(&mut self, levels: LogLevels) {
+        self.config.levels = levels;
+    }
+
+    /// Removes `--` and `/* */` comments, e.g. sqlcommenter tags, from logged
+    /// queries. Comment markers inside string literals are kept.
+    pub fn set_strip_comments(&mut self, enabled: bool) {
+        self.config.strip_comments = enabled;
+    }
+
+    /// Replaces the string and numeric literals and the bind values of logged
+    /// queries with `?`, as in their shapes, so no data ends up in the logs,
+    /// e.g. `SELECT * FROM users WHERE email = ?`. This applies to every line
+    /// which shows a query, not to events and callbacks.
+    pub fn set_redact_literals(&mut self, enabled: bool) {
+        self.config.redact_literals = enabled;
+    }
+
+    /// Only logs one in every `one_in` queries which are not slow, counted by
+    /// their sequence number, starting with the first. Slow and failed
+    /// queries are always logged, and statistics and events still see every
+    /// query. Pass `None` to log every query again.
+    pub fn set_sample_fast_queries(&mut self, one_in: Option<u32>) {
+        self.config.sample_fast_queries = one_in;
+    }
+
+    /// Sets the fixed slow query thresholds used while the connection runs in
+    /// `mode`. By default every mode uses the default thresholds of 1 s and
+    /// 5 s, see `SlowQueryThresholds` for presets. `NoLog` shares the
+    /// thresholds of `Standard`, every other mode has its own, so setting the
+    /// ones of `Profile` leaves `Standard` alone.
+    pub fn set_slow_query_thresholds(&mut self, mode: DbLogMode, thresholds: SlowQueryThresholds) {
+        self.config.thresholds.set(mode, thresholds);
+    }
+
+    /// Uses thresholds which can be changed at any time instead of the
+    /// thresholds of every log mode, e.g. one `Arc` shared by a whole pool.
+    /// `Profile` mode keeps the fixed thresholds set for it, so profiles
+    /// taken while the live thresholds change stay comparable.
+    ///
+    /// Pass `None` to use the thresholds per log mode again.
+    pub fn set_live_thresholds(&mut self, thresholds: Option<Arc<LiveThresholds>>) {
+        self.config.thresholds.set_live(thresholds);
+    }
+
+    /// Sets the fixed slow query thresholds of every log mode, e.g. to suit
+    /// the latency of a particular database. Call it before
+    /// `set_slow_query_thresholds` to override single modes.
+    pub fn set_default_slow_query_thresholds(&mut self, thresholds: SlowQueryThresholds) {
+        self.config.thresholds.set_all(thresholds);
+    }
+
+    /// Logs slow queries at `error` instead of `warn` once they take `multiplier`
+    /// times the threshold they are logged at `warn` from, i.e. the very slow
+    /// threshold in `Standard` mode and the slow one in the `Excessive` modes.
+    /// The default is 5, `None` never escalates to `error`.
+    pub fn set_critical_multiplier(&mut self, multiplier: Option<u32>) {
+        self.config.critical_multiplier = multiplier;
+    }
+
+    /// Sets the statement timeout of the database, to warn about queries which
+    /// succeeded but took at least 90% of it, before they start to fail.
+    ///
+    /// Pass `None` to not warn about such queries again.
+    pub fn set_timeout_budget(&mut self, budget: Option<Duration>) {
+        self.config.timeout_budget = budget;
+    }
+
+    /// Sets the fraction of the timeout budget from which queries are warned
+    /// about, `0.9` by default.
+    pub fn set_timeout_warn_fraction(&mut self, fraction: f64) {
+        self.config.timeout_warn_fraction = fraction;
+    }
+
+    /// Sets how the line of a query is formatted, human readable text by default.
+    pub fn set_log_format(&mut self, format: LogFormat) {
+        self.config.format = format;
+    }
+
+    /// Rewords human readable slow query lines, e.g.
+    /// `"SLOW {duration} rows={rows}: {query}"`, without a custom format.
+    /// `{duration}`, `{query}`, `{rows}` and `{timestamp}` are replaced with
+    /// the values of the query, `{rows}` with `?` if the count is unknown.
+    /// The prefixes of the line, e.g. `[SELECT]`, are kept.
+    ///
+    /// Pass `None` for the default wording, `DEFAULT_SLOW_QUERY_TEMPLATE`,
+    /// which also mentions the time the query waited for a connection.
+    pub fn set_slow_query_template(&mut self, template: Option<String>) {
+        self.config.slow_query_template = template;
+    }
+
+    /// Sets how queries are normalized into the shapes which group them for
+    /// aggregates, adaptive thresholds and rate limiting.
+    /// Set it before running queries, shapes seen so far are kept as they are.
+    pub fn set_normalization(&mut self, normalization: NormalizationConfig) {
+        self.config.normalization = normalization;
+    }
+
+    /// Appends `dur_ms=12.3` and, if known, `rows=5` to every human readable
+    /// line, so alerting can parse them regardless of the wording of the line.
+    /// Off by default.
+    pub fn set_append_machine_fields(&mut self, enabled: bool) {
+        self.config.append_machine_fields = enabled;
+    }
+
+    /// Logs queries as statements which can be pasted into `psql` and run:
+    /// bind values are inlined, `--` comments are dropped, the SQL is put on a
+    /// single line and ends with `;`.
+    /// Bind values are inlined on a best effort basis from their `Debug` output.
+    pub fn set_psql_ready(&mut self, enabled: bool) {
+        self.config.psql_ready = enabled;
+    }
+
+    /// Appends the columns a `SELECT` returns to its log line, e.g. `cols=[id,name]`,
+    /// to spot queries which fetch more than they need, like `cols=[*]`.
+    /// The columns are read from the select list of the SQL, so they are
+    /// omitted where that is not enough, e.g. for queries starting with `WITH`.
+    pub fn set_log_columns(&mut self, enabled: bool) {
+        self.config.log_columns = enabled;
+    }
+
+    /// Appends the number of bind parameters to the line of a query, e.g.
+    /// `params=2000` for a huge `IN` list, a common cause of slow planning.
+    /// It is counted from the binds diesel collects while rendering the query,
+    /// so it is shown for queries built with diesel, including `sql_query`,
+    /// but not for plain SQL run with `execute` or `batch_execute`.
+    pub fn set_show_param_count(&mut self, enabled: bool) {
+        self.config.show_param_count = enabled;
+    }
+
+    /// Appends an identity of the query to its log line, e.g. `qid=5f0e9a3c1b2d4e6f`,
+    /// which is the same for every execution of the same query. Statically typed
+    /// queries are identified by their diesel `QueryId`, which ignores the bound
+    /// values and is cheap. Other queries are identified by the fingerprint of
+    /// their normalized SQL. The identity is also in `QueryEvent::query_id`.
+    ///
+    /// `QueryId`s are only stable within one build of the application.
+    pub fn set_log_query_id(&mut self, enabled: bool) {
+        self.config.log_query_id = enabled;
+    }
+
+    /// Records how long the caller waited to check this connection out of a
+    /// pool, e.g. measured around `Pool::get`. The wait is logged with the next
+    /// query on this connection, e.g. `Query waited 120.0 ms for a connection, ran in 4.0 ms`,
+    /// which tells pool contention apart from slow SQL.
+    pub fn record_pool_wait(&self, wait: Duration) {
+        let nanos = wait.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.pool_wait.store(nanos, Ordering::Relaxed);
+    }
+
+    fn take_pool_wait(&self) -> Option<Duration> {
+        match self.pool_wait.swap(0, Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Appends an estimate of how much memory the rows of a slow `load` take,
+    /// e.g. `(~3.2 MB)`, to its line, to catch queries which return enormous
+    /// results. The estimate is the size of the returned `Vec`'s elements and
+    /// does not include heap data like the contents of `String` columns.
+    pub fn set_estimate_result_bytes(&mut self, enabled: bool) {
+        self.config.estimate_result_bytes = enabled;
+    }
+
+    /// Records the estimated size of the rows a query returned and returns
+    /// their number.
+    fn record_result_bytes<U>(&self, rows: &[U]) -> Option<usize> {
+        if self.config.estimate_result_bytes {
+            self.result_bytes
+                .store(std::mem::size_of_val(rows), Ordering::Relaxed);
+        }
+        Some(rows.len())
+    }
+
+    /// Rounds the durations of logged queries to the nearest multiple of
+    /// `bucket`, e.g. 10 ms, to reduce the cardinality of metrics derived from
+    /// the logs. Queries are still compared to the thresholds unrounded.
+    ///
+    /// Pass `None` to log exact durations again.
+    pub fn set_duration_bucket(&mut self, bucket: Option<Duration>) {
+        self.config.duration_bucket = bucket;
+    }
+
+    /// Sets the number of decimals of the durations in log lines, which is
+    /// 1 for milliseconds, 2 for seconds and none for microseconds by default.
+    pub fn set_duration_precision(&mut self, precision: usize) {
+        self.config.duration_precision = Some(precision);
+    }
+
+    /// Sets where the timestamps of log lines and events come from,
+    /// the system clock by default.
+    pub fn set_time_source(&mut self, source: Arc<dyn TimeSource>) {
+        self.config.clock = source;
+    }
+
+    /// Sets where log lines are written to, the `log` crate by default.
+    /// Connections can share one output, e.g. a file, through the `Arc`.
+    pub fn set_output(&mut self, output: Arc<LogOutput>) {
+        self.config.output = output;
+    }
+
+    /// Sets where the lines of failed queries are written to instead, e.g. to
+    /// route errors elsewhere than the rest. This covers the lines of failed
+    /// queries in the `OnError` and `Profile` modes and the lines about
+    /// deadlocks, lock timeouts and dead connections.
+    ///
+    /// Pass `None` to write them to the output of all other lines again,
+    /// which is the default.
+    pub fn set_error_output(&mut self, output: Option<Arc<LogOutput>>) {
+        self.config.error_output = output;
+    }
+
+    /// Whether the lines printed in the `Excessive` modes and written to a
+    /// `LogOutput::Writer` start with their timestamp. Turn it off if whatever
+    /// collects the output adds timestamps itself. Enabled by default.
+    pub fn set_show_timestamp(&mut self, enabled: bool) {
+        self.config.show_timestamp = enabled;
+    }
+
+    /// Buffers the SQL of up to `max_statements` queries per transaction and
+    /// dumps them at `warn` if the transaction is rolled back.
+    /// Committed transactions discard their buffer without logging anything.
+    /// This works independently of the log mode, so even `NoLog` connections
+    /// report the statements of failed transactions.
+    ///
+    /// Pass `None` to disable the buffer again.
+    pub fn set_failed_transaction_buffer(&mut self, max_statements: Option<usize>) {
+        self.transaction_buffer.set_limit(max_statements);
+    }
+
+    /// Emits a `warn` when a transaction is committed after running more than
+    /// `max_queries` queries. Queries run inside a savepoint count towards the
+    /// enclosing transaction.
+    ///
+    /// Pass `None` to disable the check again.
+    pub fn set_max_queries_per_transaction(&mut self, max_queries: Option<usize>) {
+        self.transaction_queries.set_limit(max_queries);
+    }
+
+    /// Additionally flags queries as slow when they take considerably longer
+    /// than previous executions of the same query shape, see `AdaptiveThresholds`.
+    /// Such queries are logged like slow queries even if they stay below the
+    /// fixed thresholds.
+    ///
+    /// Pass `None` to only use the fixed thresholds again.
+    pub fn set_adaptive_thresholds(&mut self, thresholds: Option<AdaptiveThresholds>) {
+        self.adaptive.set_deviation(thresholds);
+    }
+
+    /// Additionally flags queries as slow when they take a multiple of the
+    /// moving average of the same query shape, see `MovingAverageThreshold`.
+    /// Such queries are logged like slow queries even if they stay below the
+    /// fixed thresholds.
+    ///
+    /// Pass `None` to disable the rule again.
+    pub fn set_moving_average_threshold(&mut self, threshold: Option<MovingAverageThreshold>) {
+        self.adaptive.set_moving_average(threshold);
+    }
+
+    /// Returns true if the last query was slow, either by the fixed threshold or
+    /// relative to its baseline, no matter at which level it was logged.
+    /// Only queries which were timed are considered, i.e. with logging enabled,
+    /// in `Silent` mode or while statistics are collected otherwise.
+    pub fn last_query_was_slow(&self) -> bool {
+        self.last_query_was_slow.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if a query run now could be logged, to do extra diagnostic
+    /// work only then. It takes the same decisions as running a query: the log
+    /// mode, `without_logging`, suppressed migrations, the minimum transaction
+    /// depth and the active window. The log predicate and one-shot overrides
+    /// depend on the next query and are not considered.
+    pub fn is_logging(&self) -> bool {
+        !self.current_mode().do_not_log()
+            && self
+                .config
+                .is_in_active_window(clock::now(&*self.config.clock))
+    }
+
+    /// Returns the slowest query this connection has run so far.
+    /// Like `last_query_was_slow`, only queries which were timed are considered.
+    pub fn slowest_query(&self) -> Option<SlowQueryRecord> {
+        self.slowest_query.get()
+    }
+
+    /// Returns the slowest query so far and starts tracking from scratch,
+    /// e.g. to report the worst query of every interval.
+    pub fn take_slowest_query(&self) -> Option<SlowQueryRecord> {
+        self.slowest_query.take()
+    }
+
+    /// Logs the `top` slowest individual queries of every `interval` at `info`,
+    /// the slowest first, e.g. `Some((5, Duration::from_secs(60)))` for the five
+    /// worst queries of every minute. Unlike `query_aggregates`, which group
+    /// executions by their shape, this lists single executions with their time.
+    ///
+    /// No thread is spawned: the digest of a window is logged with the first
+    /// query after it ended, which then starts the next window. Idle connections
+    /// log nothing. Only queries which were timed are considered, as for
+    /// `slowest_query`.
+    ///
+    /// Pass `None` to stop, which drops the current window.
+    pub fn set_worst_queries_digest(&mut self, digest: Option<(usize, Duration)>) {
+        self.config.worst_queries_digest = digest;
+        self.worst_queries = worst::WorstQueries::default();
+    }
+
+    /// The cumulative time spent running reads, i.e. `load` and the other
+    /// `query_by_*` methods, since the connection was created or the last
+    /// `take_total_query_time`. It is tracked in every log mode.
+    pub fn read_total_time(&self) -> Duration {
+        self.total_time.get(Access::Read)
+    }
+
+    /// The cumulative time spent running writes, i.e. `execute`,
+    /// `execute_returning_count` and `batch_execute`, since the connection
+    /// was created or the last `take_total_query_time`. It is tracked in every
+    /// log mode.
+    pub fn write_total_time(&self) -> Duration {
+        self.total_time.get(Access::Write)
+    }
+
+    /// Keeps the duration and outcome of every query of the last `window`,
+    /// for `health_summary`. This costs a few bytes per query in the window.
+    ///
+    /// Pass `None` to stop tracking and forget the window.
+    pub fn set_health_window(&mut self, window: Option<Duration>) {
+        self.health.set_window(window);
+    }
+
+    /// Summarizes the queries of the window set with `set_health_window`,
+    /// e.g. for a health check endpoint: their number, errors, slow ones and
+    /// 95th percentile duration. The window is not reset.
+    ///
+    /// Returns `None` if no window is set.
+    pub fn health_summary(&self) -> Option<HealthSummary> {
+        self.health.summary()
+    }
+
+    /// The cumulative time spent running queries of any kind since the
+    /// connection was created or the last `take_total_query_time`, e.g. to
+    /// compute the fraction of a request spent in the database. It is tracked
+    /// in every log mode.
+    pub fn total_query_time(&self) -> Duration {
+        self.total_time.total()
+    }
+
+    /// Returns the `total_query_time` so far and starts counting it from zero,
+    /// e.g. to report the time spent in the database per request. The read and
+    /// write totals are reset with it, the query counts are not.
+    pub fn take_total_query_time(&self) -> Duration {
+        self.total_time.take_total()
+    }
+
+    /// Tracks the time spent rendering, formatting and emitting the lines of
+    /// queries and everything else this crate does around them, for
+    /// `logging_overhead`. This reads the clock twice more per query.
+    pub fn set_track_logging_overhead(&mut self, enabled: bool) {
+        self.track_logging_overhead = enabled;
+    }
+
+    /// The cumulative time spent around queries by this crate, rather than
+    /// running them, since `set_track_logging_overhead` was enabled, e.g. to
+    /// judge whether logging should move off the query thread. This time is
+    /// not part of `total_query_time` or the logged durations.
+    pub fn logging_overhead(&self) -> Duration {
+        Duration::from_nanos(self.logging_overhead.load(Ordering::Relaxed))
+    }
+
+    /// The number of queries run since the connection was created, failed ones
+    /// included, so `total_query_time() / query_count()` is their average duration.
+    /// It is tracked in every log mode.
+    pub fn query_count(&self) -> u64 {
+        self.total_time.queries()
+    }
+
+    /// The number of queries which took longer than the slow threshold of the
+    /// mode they ran in, since the connection was created. Very slow queries
+    /// are included. It is tracked in every log mode.
+    pub fn slow_query_count(&self) -> u64 {
+        self.total_time.slow_queries()
+    }
+
+    /// The number of queries which took longer than the very slow threshold of
+    /// the mode they ran in, since the connection was created, e.g. to alert
+    /// on them separately from `slow_query_count`. It is tracked in every log mode.
+    pub fn very_slow_query_count(&self) -> u64 {
+        self.total_time.very_slow_queries()
+    }
+
+    /// Sends a `QueryEvent` for every query run from now on to the returned
+    /// receiver, independently of the log mode. Events are processed wherever
+    /// the receiver lives, e.g. batched on another thread.
+    ///
+    /// Once the receiver is dropped, no further events are sent.
+    pub fn with_event_channel(mut self) -> (Self, std::sync::mpsc::Receiver<QueryEvent>) {
+        let receiver = self.events.open();
+        (self, receiver)
+    }
+
+    /// Keeps the `QueryEvent` of every query run from now on, independently of
+    /// the log mode, until `drain_events` takes them, e.g. to assert on them in
+    /// tests or to export them in batches. At most `capacity` events are kept,
+    /// the oldest ones are dropped first, so an undrained buffer does not grow.
+    ///
+    /// Pass `None` to stop keeping events, which drops the ones kept so far.
+    pub fn set_buffer_events(&mut self, capacity: Option<usize>) {
+        self.event_buffer.set_capacity(capacity);
+    }
+
+    /// Returns the events kept since the last call, oldest first, and forgets
+    /// them. Empty unless `set_buffer_events` was called.
+    pub fn drain_events(&self) -> Vec<QueryEvent> {
+        self.event_buffer.drain()
+    }
+
+    /// Appends `key=value` to the line of every query, after the pairs added
+    /// before, e.g. the service name, region or shard of the connection.
+    /// In logfmt lines the value is quoted.
+    pub fn with_context(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.context.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sends the `QueryEvent` of every query run from now on to `sink` as well,
+    /// independently of the log mode and of the sinks added before, e.g.
+    /// `conn.add_sink(Arc::new(FileSink::open("queries.log")?))`.
+    pub fn add_sink(&mut self, sink: Arc<dyn QuerySink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Calls `callback` for every slow query, by the fixed threshold or relative
+    /// to its baseline, independently of the log mode. Fast queries never reach it.
+    ///
+    /// The callback runs on the thread which ran the query, before the query
+    /// returns, so it should be quick, e.g. bump a counter or capture a backtrace.
+    pub fn set_on_slow_query(&mut self, callback: Option<SlowQueryCallback>) {
+        self.on_slow_query = callback;
+    }
+
+    /// Decides with `classifier` instead of the thresholds which queries are
+    /// slow, e.g. updates above 100 ms and any query above 2 s:
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// # use diesel::prelude::*;
+    /// # use diesel::sqlite::SqliteConnection;
+    /// # use diesel_logger::{DbLogMode, LoggingConnection, QueryEvent, QueryKind};
+    /// # let conn = SqliteConnection::establish(":memory:").unwrap();
+    /// # let mut conn = LoggingConnection::new(conn, DbLogMode::Standard);
+    /// conn.set_slow_classifier(Some(Arc::new(|e: &QueryEvent| {
+    ///     e.duration > Duration::from_secs(2)
+    ///         || (e.kind == QueryKind::Update && e.duration > Duration::from_millis(100))
+    /// })));
+    /// ```
+    ///
+    /// It gets the event of every query with `slow` set by the thresholds, so
+    /// it can refine their verdict.
+    ///
+    /// Its verdict decides the level and wording of the line of a query, the
+    /// slow query callbacks and `last_query_was_slow`. Slow queries are logged
+    /// at the level of slow or, above the very slow threshold, of very slow
+    /// queries. The slow query counts still follow the thresholds.
+    ///
+    /// Pass `None` to judge by the thresholds again, which is the default.
+    pub fn set_slow_classifier(&mut self, classifier: Option<SlowQueryClassifier>) {
+        self.slow_classifier = classifier;
+    }
+
+    /// Sends a `QueryEvent` for every slow query to `sender`, like
+    /// `set_on_slow_query`, so async code can react to slow queries.
+    /// Sending never blocks the query. Once the receiver is dropped,
+    /// no further events are sent.
+    #[cfg(feature = "tokio")]
+    pub fn set_slow_query_sender(
+        &mut self,
+        sender: Option<tokio::sync::mpsc::UnboundedSender<QueryEvent>>,
+    ) {
+        self.async_slow_queries.set(sender);
+    }
+
+    /// Only logs the queries whose SQL `predicate` returns true for, e.g. to
+    /// sample queries or to log the ones of a single tenant. Queries it
+    /// returns false for are treated as in `NoLog` mode, failed ones included.
+    /// The predicate is not consulted in `NoLog` and `Silent` mode, but
+    /// otherwise every query has to be rendered for it.
+    ///
+    /// Pass `None` to log queries by the log mode alone again.
+    pub fn set_log_predicate(&mut self, predicate: Option<LogPredicate>) {
+        self.log_predicate = predicate;
+    }
+
+    /// Renders the errors in the lines of failed queries with `formatter`
+    /// instead of their `Display` impl, e.g. to shorten them or to map them
+    /// to the error taxonomy of the application.
+    ///
+    /// Pass `None` to use `Display` again.
+    pub fn set_error_formatter(&mut self, formatter: Option<ErrorFormatter>) {
+        self.error_formatter = formatter;
+    }
+
+    /// Calls `callback` with the duration of every successful commit, including
+    /// the release of savepoints, e.g. to feed a histogram. Commits can be slow
+    /// on their own, waiting for fsync or replication.
+    pub fn set_on_commit(&mut self, callback: Option<CommitCallback>) {
+        self.on_commit = callback;
+    }
+
+    /// Only logs queries run at a transaction depth of at least `min_depth`,
+    /// e.g. `1` for queries inside transactions or `2` for queries inside
+    /// savepoints. Statistics, events and callbacks are not affected.
+    ///
+    /// Pass `None` to log queries at every depth again.
+    pub fn set_min_transaction_depth(&mut self, min_depth: Option<u32>) {
+        self.config.min_transaction_depth = min_depth;
+    }
+
+    /// Logs a warning for commits which take at least `threshold`.
+    ///
+    /// Pass `None` to not warn about slow commits again.
+    pub fn set_slow_commit_threshold(&mut self, threshold: Option<Duration>) {
+        self.config.slow_commit_threshold = threshold;
+    }
+
+    /// Logs every query at `debug` right before it is run, in addition to the
+    /// line with its duration afterwards. If a query hangs, the last of these
+    /// lines shows which one. This renders every query and doubles the lines.
+    pub fn set_log_before_execute(&mut self, enabled: bool) {
+        self.config.log_before_execute = enabled;
+    }
+
+    /// Only logs queries started between `start` and `end` UTC each day, e.g.
+    /// during a nightly job which is being investigated. A window from 22:00
+    /// to 02:00 spans midnight. Statistics, events and callbacks are not affected.
+    ///
+    /// Pass `None` to log queries at any time again. Requires the `chrono`
+    /// feature, which is on by default.
+    #[cfg(feature = "chrono")]
+    pub fn set_active_window(&mut self, window: Option<(chrono::NaiveTime, chrono::NaiveTime)>) {
+        use chrono::Timelike;
+
+        let since_midnight = |time: chrono::NaiveTime| {
+            Duration::new(
+                u64::from(time.num_seconds_from_midnight()),
+                time.nanosecond(),
+            )
+        };
+        self.config.active_window =
+            window.map(|(start, end)| (since_midnight(start), since_midnight(end)));
+    }
+
+    /// Warns about every `UPDATE` or `DELETE` which affected no rows, which is
+    /// often a bug, e.g. a stale ID. This renders every write.
+    pub fn set_warn_on_zero_affected(&mut self, enabled: bool) {
+        self.config.warn_on_zero_affected = enabled;
+    }
+
+    /// Warns about every `UPDATE` or `DELETE` which affected more than
+    /// `max_rows` rows, e.g. an accidental update of a whole table.
+    /// This renders every write.
+    ///
+    /// Pass `None` to not warn about them again.
+    pub fn set_max_affected_rows(&mut self, max_rows: Option<usize>) {
+        self.config.max_affected_rows = max_rows;
+    }
+
+    /// Warns about every `UPDATE` or `DELETE` run with `execute` or
+    /// `execute_returning_count` which has no `WHERE` clause, before it is run.
+    /// A `WHERE` in a comment, string literal or subquery does not count.
+    /// This renders every write.
+    pub fn set_warn_on_missing_where(&mut self, enabled: bool) {
+        self.config.warn_on_missing_where = enabled;
+    }
+
+    /// Refuses to run an `UPDATE` or `DELETE` without a `WHERE` clause, like
+    /// `set_warn_on_missing_where` finds them, and returns a
+    /// `QueryBuilderError` instead. The refusal is logged at `error`, unless
+    /// the log mode logs nothing. Statements of `batch_execute`, e.g. of
+    /// migrations, are not checked.
+    pub fn set_refuse_missing_where(&mut self, enabled: bool) {
+        self.config.refuse_missing_where = enabled;
+    }
+
+    /// Records the SQL of every failed query as the `db.statement` field of
+    /// the current `tracing` span, so error reporters which read span fields,
+    /// e.g. Sentry's, see the offending query. `tracing` only records fields
+    /// a span declares, so declare it, e.g. `db.statement = tracing::field::Empty`.
+    /// Queries which fail in `NoLog` or `Silent` mode are not recorded.
+    #[cfg(feature = "tracing-events")]
+    pub fn set_record_failed_query_on_span(&mut self, enabled: bool) {
+        self.config.record_failed_query_on_span = enabled;
+    }
+
+    /// Marks the line of every query with its sequence number on the
+    /// connection, as `#42` or `seq=42` in logfmt lines, which orders queries
+    /// whose timestamps collide. `QueryEvent`s always carry it.
+    pub fn set_show_seq(&mut self, enabled: bool) {
+        self.config.show_seq = enabled;
+    }
+
+    /// Marks the human readable lines of hand-written SQL, i.e. of
+    /// `sql_query(..).load`, `Connection::execute` and `batch_execute`, with a
+    /// `[raw]` prefix, so reviewers can spot it. Structured output always
+    /// marks it, with `raw=true` in logfmt and as the `raw` field with the `kv`
+    /// and `tracing-events` features. Queries built with the query builder are
+    /// not marked, and neither is `sql_query(..).execute`, which runs through
+    /// the same method as them.
+    pub fn set_mark_raw_queries(&mut self, enabled: bool) {
+        self.config.mark_raw_queries = enabled;
+    }
+
+    /// Marks every line with the backend of the connection, e.g. `pg` or
+    /// `sqlite`, to tell the connections to several databases apart:
+    /// as a `[pg]` prefix, or as `backend=pg` in logfmt lines of queries.
+    pub fn set_show_backend(&mut self, enabled: bool) {
+        self.config.show_backend = enabled;
+    }
+
+    /// Logs a separate line at `error` when a query fails with an error which
+    /// means that the connection is likely dead, e.g. after a database restart,
+    /// to tell those apart from queries which are wrong. Enabled by default.
+    pub fn set_flag_dead_connections(&mut self, enabled: bool) {
+        self.config.flag_dead_connections = enabled;
+    }
+
+    /// Logs one line per committed transaction at `info` with the number of
+    /// queries it ran, their total and slowest duration and how long the
+    /// transaction was open, e.g. to use instead of the lines of every query.
+    pub fn set_summarize_transactions(&mut self, enabled: bool) {
+        self.transaction_summaries.set_enabled(enabled);
+    }
+
+    /// Logs how long every outermost transaction was open at `debug` when it
+    /// is committed or rolled back. Savepoints are part of their transaction.
+    pub fn set_log_transaction_duration(&mut self, enabled: bool) {
+        self.config.log_transaction_duration = enabled;
+    }
+
+    /// Stores the SQL of every query in a thread-local before running it,
+    /// see `last_executed_sql`. This renders every query, even if it is not logged.
+    pub fn set_track_last_sql(&mut self, enabled: bool) {
+        self.track_last_sql = enabled;
+    }
+
+    /// Runs `f` and returns the SQL of every query it ran on this connection,
+    /// in order, regardless of the log mode. Meant for asserting on the
+    /// statements a piece of code issues in tests.
+    pub fn capture_queries<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<String>) {
+        self.capture.capture(f)
+    }
+
+    /// Runs `f`, which runs migrations on this connection, e.g. with
+    /// `diesel_migrations::run_pending_migrations(&conn)`. If migration logs
+    /// are suppressed, only its failed queries are logged, like in `OnError` mode.
+    pub fn run_as_migrations<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.migrations.run(f)
+    }
+
+    /// Runs `f` with the queries it runs on this connection not logged, as in
+    /// `NoLog` mode, e.g. a bulk operation where the lines of every row would
+    /// dominate. The mode of the connection applies again afterwards, even if
+    /// `f` panics.
+    pub fn without_logging<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.without_logging.run(f)
+    }
+
+    /// Returns a view of this connection whose queries are not logged, e.g.
+    /// `conn.no_log().execute("SELECT 1")` in a polling loop. Unlike
+    /// `without_logging` it does not affect queries run concurrently on the
+    /// connection. The queries run directly on the wrapped connection, so they
+    /// are not counted or timed either.
+    pub fn no_log(&self) -> Unlogged<'_, C> {
+        Unlogged(&self.conn)
+    }
+
+    /// Only logs the failed queries of `run_as_migrations`, e.g. to keep the
+    /// DDL of migrations out of the logs at startup.
+    pub fn set_suppress_migration_logs(&mut self, enabled: bool) {
+        self.config.suppress_migration_logs = enabled;
+    }
+
+    /// Aggregates the count, total and maximum duration of every query shape,
+    /// independently of the log mode.
+    pub fn set_collect_aggregates(&mut self, enabled: bool) {
+        self.aggregates.set_enabled(enabled);
+    }
+
+    /// Returns the aggregates collected so far, the query shapes with the
+    /// highest total time first.
+    pub fn query_aggregates(&self) -> Vec<QueryAggregate> {
+        self.aggregates.snapshot()
+    }
+
+    /// Logs the slow queries the rate limit still holds back and, if
+    /// aggregates were collected, the query shapes with the highest total time
+    /// at `info`. Call it at the end of short-lived scripts and one-off
+    /// profiling runs, or use `into_inner`, which reports as well.
+    pub fn report(&self) {
+        for suppressed in self.slow_query_limiter.drain() {
+            self.config.emit_message(
+                log::Level::Warn,
+                self.log_mode,
+                clock::now(&*self.config.clock),
+                &suppressed.message(),
+            );
+        }
+        let aggregates = self.aggregates.snapshot();
+        if !aggregates.is_empty() {
+            let report =
+                aggregate::report(&aggregates, REPORT_SIZE, self.config.duration_precision);
+            self.config.emit_message(
+                log::Level::Info,
+                self.log_mode,
+                clock::now(&*self.config.clock),
+                &report,
+            );
+        }
+    }
+
+    /// Logs the `report` of this connection and returns the wrapped connection.
+    pub fn into_inner(self) -> C {
+        self.report();
+        self.conn
+    }
+
+    /// Logs at most `max_per_window` slow executions of the same query shape per
+    /// window and summarizes the others in a single line, so a slow query in a
+    /// tight loop does not flood the log. Pass `None` to log every slow query.
+    pub fn set_slow_query_rate_limit(&mut self, limit: Option<SlowQueryRateLimit>) {
+        self.slow_query_limiter.set_config(limit);
+    }
+
+    /// Returns true if queries run in `mode` have to be timed and rendered,
+    /// either to log them or to report them somewhere else.
+    fn is_instrumented(&self, mode: DbLogMode) -> bool {
+        logs_every_query(mode)
+            || mode == DbLogMode::Silent
+            || self.events.is_open()
+            || self.event_buffer.is_enabled()
+            || !self.sinks.is_empty()
+            || self.aggregates.is_enabled()
+            || self.on_slow_query.is_some()
+            || self.async_slow_queries.is_open()
+            || self.track_last_sql
+    }
+
+    /// The log mode of the connection. It is `NoLog` inside `without_logging`
+    /// and outside of the transaction depth queries are logged from, and
+    /// `OnError` while running suppressed migrations.
+    fn current_mode(&self) -> DbLogMode {
+        if self.without_logging.is_active() {
+            return DbLogMode::NoLog;
+        }
+        if self.config.suppress_migration_logs
+            && self.migrations.is_active()
+            && !self.log_mode.do_not_log()
+        {
+            return DbLogMode::OnError;
+        }
+        match self.config.min_transaction_depth {
+            Some(min) if self.conn.transaction_manager().get_transaction_depth() < min => {
+                DbLogMode::NoLog
+            }
+            _ => self.log_mode,
+        }
+    }
+
+    /// Takes the mode set by `log_next`, if any.
+    fn take_next_log_mode(&self) -> Option<DbLogMode> {
+        if self.next_log_mode.load(Ordering::Relaxed) == NO_NEXT_LOG_MODE {
+            return None;
+        }
+        DbLogMode::from_u8(self.next_log_mode.swap(NO_NEXT_LOG_MODE, Ordering::Relaxed))
+    }
+
+    /// Whether the SQL of every query is needed, not just of the logged ones.
+    fn needs_every_query(&self) -> bool {
+        self.slow_classifier.is_some()
+            || self.config.worst_queries_digest.is_some()
+            || self.events.is_open()
+            || self.event_buffer.is_enabled()
+            || !self.sinks.is_empty()
+            || self.aggregates.is_enabled()
+            || self.adaptive.is_enabled()
+            || self.track_last_sql
+    }
+
+    /// The fixed thresholds of a log mode.
+    fn thresholds(&self, mode: DbLogMode) -> SlowQueryThresholds {
+        self.config.thresholds.get(mode)
+    }
+
+    /// Runs a query like `run_and_log`, and adds the time spent around the
+    /// query itself to the logging overhead, if it is tracked.
+    #[allow(clippy::too_many_arguments)]
+    fn maybe_log<'q, R>(
+        &self,
+        access: Access,
+        kind: fn(&str) -> QueryKind,
+        static_id: Option<TypeId>,
+        raw: bool,
+        render: impl FnOnce() -> Cow<'q, str>,
+        run: impl FnOnce() -> QueryResult<R>,
+        rows: impl FnOnce(&R) -> Option<usize>,
+    ) -> QueryResult<R> {
+        if !self.track_logging_overhead {
+            return self.run_and_log(access, kind, static_id, raw, render, run, rows);
+        }
+        let entered = std::time::Instant::now();
+        let ran_for = std::cell::Cell::new(Duration::from_secs(0));
+        let run = || {
+            let start_time = std::time::Instant::now();
+            let result = run();
+            ran_for.set(start_time.elapsed());
+            result
+        };
+        let result = self.run_and_log(access, kind, static_id, raw, render, run, rows);
+        let overhead = entered.elapsed().saturating_sub(ran_for.get());
+        let nanos = overhead.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.logging_overhead.fetch_add(nanos, Ordering::Relaxed);
+        result
+    }
+
+    /// Runs a query and decides in one place whether it is timed, rendered,
+    /// buffered and logged, so every `Connection` method behaves the same.
+    /// `render` is only called if the SQL of the query is needed,
+    /// `kind` derives the kind of the query from it. `raw` marks hand-written SQL.
+    #[allow(clippy::too_many_arguments)]
+    fn run_and_log<'q, R>(
+        &self,
+        access: Access,
+        kind: fn(&str) -> QueryKind,
+        static_id: Option<TypeId>,
+        raw: bool,
+        render: impl FnOnce() -> Cow<'q, str>,
+        run: impl FnOnce() -> QueryResult<R>,
+        rows: impl FnOnce(&R) -> Option<usize>,
+    ) -> QueryResult<R> {
+        self.transaction_queries.increment();
+        let seq = self.query_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        let thread_mode = one_shot::take();
+        let mode = self
+            .take_next_log_mode()
+            .or(thread_mode)
+            .unwrap_or_else(|| self.current_mode());
+        let mut render = Some(render);
+        let mut rendered = None;
+        let mode = match &self.log_predicate {
+            Some(predicate) if !mode.do_not_log() => {
+                let debug_query = render.take().map(|render| render()).unwrap_or_default();
+                let logged = predicate(&debug_query);
+                rendered = Some(debug_query);
+                if logged {
+                    mode
+                } else {
+                    DbLogMode::NoLog
+                }
+            }
+            _ => mode,
+        };
+        // The predicate may have rendered the query already.
+        let render = move || {
+            rendered
+                .or_else(|| render.map(|render| render()))
+                .unwrap_or_default()
+        };
+        let run = || {
+            let start_time = std::time::Instant::now();
+            let result = run();
+            let duration = start_time.elapsed();
+            let nanos = duration.as_nanos().min(u128::from(u64::MAX)) as u64;
+            self.last_query_nanos.store(nanos, Ordering::Relaxed);
+            let thresholds = self.thresholds(mode);
+            self.total_time.add(access, duration, &thresholds);
+            self.transaction_summaries.record(duration);
+            self.health
+                .record(duration, result.is_err(), thresholds.is_slow(duration));
+            if let Err(error) = &result {
+                self.log_dead_connection(mode, error);
+            }
+            result
+        };
+        let expects_rows = one_shot::take_expects_rows();
+        let buffer = self.transaction_buffer.is_recording();
+        let capture = self.capture.is_active();
+        let log_before =
+            self.config.log_before_execute && logs_every_query(mode) && mode != DbLogMode::Profile;
+        let check_affected = access == Access::Write
+            && !mode.do_not_log()
+            && (self.config.warn_on_zero_affected || self.config.max_affected_rows.is_some());
+        let prepares = self.prepared_statements.is_first_execution(static_id) && !mode.do_not_log();
+        let check_where = access == Access::Write
+            && (self.config.warn_on_missing_where || self.config.refuse_missing_where);
+        let needs_sql = buffer
+            || capture
+            || expects_rows
+            || log_before
+            || check_affected
+            || prepares
+            || check_where;
+        if !self.is_instrumented(mode) && !needs_sql {
+            self.take_pool_wait();
+            if mode != DbLogMode::OnError {
+                return run();
+            }
+            // Only failed queries are rendered and logged.
+            let time_utc = clock::now(&*self.config.clock);
+            let start_time = std::time::Instant::now();
+            let in_flight = InFlight::new(&self.config, mode, render);
+            let result = run();
+            let render = in_flight.finish();
+            if let Err(error) = &result {
+                let debug_query = render();
+                let kind = kind(&debug_query);
+                self.record_failure_on_span(mode, &debug_query);
+                self.log_lock_error(mode, &debug_query, time_utc, error);
+                self.log_failed(
+                    mode,
+                    &debug_query,
+                    kind,
+                    start_time.elapsed(),
+                    time_utc,
+                    error,
+                );
+            }
+            return result;
+        }
+
+        if mode == DbLogMode::Standard && !needs_sql && !self.needs_every_query() {
+            // Fast queries are not even rendered if their line is disabled,
+            // which is the common case of `Standard` mode in production.
+            let time_utc = clock::now(&*self.config.clock);
+            let start_time = std::time::Instant::now();
+            let in_flight = InFlight::new(&self.config, mode, render);
+            let result = run();
+            let duration = start_time.elapsed();
+            let render = in_flight.finish();
+            let needs_query = self.thresholds(mode).is_slow(duration)
+                || self.config.output.enabled(self.config.levels.normal, mode)
+                || self.slowest_query.is_slower(duration)
+                || self.config.is_near_timeout(duration)
+                || matches!(&result, Err(error) if lock_error::classify(error).is_some())
+                || (result.is_err() && self.config.records_failures_on_span());
+            if needs_query {
+                let debug_query = render();
+                let outcome = result.as_ref().map(rows);
+                self.log_executed(
+                    mode,
+                    &debug_query,
+                    kind(&debug_query),
+                    static_id,
+                    raw,
+                    seq,
+                    start_time,
+                    duration,
+                    time_utc,
+                    outcome,
+                );
+            } else {
+                self.last_query_was_slow.store(false, Ordering::Relaxed);
+                self.take_pool_wait();
+            }
+            return result;
+        }
+
+        let debug_query = render();
+        if self.track_last_sql {
+            last_sql::set(&debug_query);
+        }
+        let kind = kind(&debug_query);
+        let time_utc = clock::now(&*self.config.clock);
+        // Judged by the SQL itself, not by the kind a caller gave.
+        let lacks_where = check_where
+            && matches!(
+                QueryKind::classify(&debug_query),
+                QueryKind::Update | QueryKind::Delete
+            )
+            && missing_where::lacks_where(&debug_query);
+        if lacks_where && self.config.refuse_missing_where {
+            let message = format!(
+                "Refused to run {} without a WHERE clause: {}",
+                kind.as_str().to_ascii_uppercase(),
+                self.config.quoted(&debug_query)
+            );
+            if !mode.do_not_log() {
+                self.config
+                    .emit_message(log::Level::Error, mode, time_utc, &message);
+            }
+            return Err(diesel::result::Error::QueryBuilderError(message.into()));
+        }
+        if lacks_where && !mode.do_not_log() {
+            self.config.emit_message(
+                log::Level::Warn,
+                mode,
+                time_utc,
+                &format!(
+                    "{} without a WHERE clause affects every row: {}",
+                    kind.as_str().to_ascii_uppercase(),
+                    self.config.quoted(&debug_query)
+                ),
+            );
+        }
+        if log_before {
+            self.log_running(mode, &debug_query, time_utc);
+        }
+        let start_time = std::time::Instant::now();
+        let in_flight = InFlight::new(&self.config, mode, || Cow::Borrowed(&*debug_query));
+        let result = run();
+        let duration = start_time.elapsed();
+        drop(in_flight);
+
+        let outcome = result.as_ref().map(rows);
+        if prepares {
+            self.log_prepare(mode, &debug_query, duration, time_utc);
+        }
+        self.log_executed(
+            mode,
+            &debug_query,
+            kind,
+            static_id,
+            raw,
+            seq,
+            start_time,
+            duration,
+            time_utc,
+            outcome,
+        );
+        if let (true, Ok(Some(affected))) = (check_affected, outcome) {
+            self.log_affected_rows(mode, kind, &debug_query, affected, time_utc);
+        }
+        if expects_rows && matches!(outcome, Ok(Some(0))) {
+            self.config.emit_message(
+                log::Level::Warn,
+                mode,
+                time_utc,
+                &format!(
+                    "Query expected to return rows returned none: {}",
+                    self.config.quoted(&debug_query)
+                ),
+            );
+        }
+        if capture {
+            self.capture.record(&debug_query);
+        }
+        if buffer {
+            self.transaction_buffer.record(debug_query.into_owned());
+        }
+        result
+    }
+
+    /// Reports the duration of a successful commit.
+    fn log_commit(&self, duration: Duration, start_time: clock::Timestamp) {
+        if let Some(callback) = &self.on_commit {
+            callback(duration);
+        }
+        let slow =
+            matches!(self.config.slow_commit_threshold, Some(threshold) if duration >= threshold);
+        if slow && !self.log_mode.do_not_log() {
+            self.config.emit_message(
+                log::Level::Warn,
+                self.log_mode,
+                start_time,
+                &format!(
+                    "Slow commit took {}",
+                    format_duration_auto(duration, self.config.duration_precision)
+                ),
+            );
+        }
+    }
+
+    /// Logs how long the outermost transaction was open, if it was timed.
+    fn log_transaction_end(&self, outcome: &str) {
+        let duration = match self.transaction_timer.stop() {
+            Some(duration) => duration,
+            None => return,
+        };
+        if !self.log_mode.do_not_log() {
+            self.config.emit_message(
+                log::Level::Debug,
+                self.log_mode,
+                clock::now(&*self.config.clock),
+                &format!(
+                    "Transaction {} after {}",
+                    outcome,
+                    format_duration_auto(duration, self.config.duration_precision)
+                ),
+            );
+        }
+    }
+
+    /// Warns about an `UPDATE` or `DELETE` which affected no rows or more
+    /// rows than allowed.
+    fn log_affected_rows(
+        &self,
+        mode: DbLogMode,
+        kind: QueryKind,
+        query: &str,
+        affected: usize,
+        start_time: clock::Timestamp,
+    ) {
+        if !matches!(kind, QueryKind::Update | QueryKind::Delete) {
+            return;
+        }
+        let kind = kind.as_str().to_ascii_uppercase();
+        let query = self.config.quoted(query);
+        let message = match self.config.max_affected_rows {
+            Some(max_rows) if affected > max_rows => format!(
+                "{} affected {} rows, more than the limit of {}: {}",
+                kind, affected, max_rows, query
+            ),
+            _ if affected == 0 && self.config.warn_on_zero_affected => {
+                format!("{} affected no rows: {}", kind, query)
             }
-        } else {
-            DbLogMode::NoLog
+            _ => return,
+        };
+        self.config
+            .emit_message(log::Level::Warn, mode, start_time, &message);
+    }
+
+    /// Logs the first execution of a statement at `debug`, tagged `prepare`,
+    /// separately from the line of the query.
+    fn log_prepare(
+        &self,
+        mode: DbLogMode,
+        query: &str,
+        duration: Duration,
+        start_time: clock::Timestamp,
+    ) {
+        if !self.config.output.enabled(log::Level::Debug, mode)
+            || !self.config.is_in_active_window(start_time)
+        {
+            return;
         }
+        let query = self.config.quoted(query);
+        let duration = format_duration_auto(duration, self.config.duration_precision);
+        self.config.emit_message(
+            log::Level::Debug,
+            mode,
+            start_time,
+            &format!("[prepare] Prepared and ran in {}: {}", duration, query),
+        );
     }
 
-    pub fn do_not_log(self) -> bool {
-        self == DbLogMode::NoLog
+    /// Logs a query at `debug` before it is run.
+    fn log_running(&self, mode: DbLogMode, query: &str, start_time: clock::Timestamp) {
+        if !self.config.output.enabled(log::Level::Debug, mode)
+            || !self.config.is_in_active_window(start_time)
+        {
+            return;
+        }
+        let query = self.config.quoted(query);
+        self.config.emit_message(
+            log::Level::Debug,
+            mode,
+            start_time,
+            &format!("Running query: {}", query),
+        );
     }
-}
 
-/// Wraps a diesel `Connection` to time and log each query using
-/// the configured logger for the `log` crate.
-///
-/// Currently, this produces a `debug` log on every query,
-/// an `info` on queries that take longer than 1 second,
-/// and a `warn`ing on queries that take longer than 5 seconds.
-/// These thresholds will be configurable in a future version.
-pub struct LoggingConnection<C: Connection> {
-    pub conn: C,
-    pub log_mode: DbLogMode,
-}
+    /// Logs a distinct line at `error` if a query failed because the
+    /// connection looks dead, separate from the line of the failed query.
+    fn log_dead_connection(&self, mode: DbLogMode, error: &diesel::result::Error) {
+        if !self.config.flag_dead_connections
+            || mode.do_not_log()
+            || !dead_connection::is_dead_connection(error)
+        {
+            return;
+        }
+        self.config.emit_failure(
+            log::Level::Error,
+            mode,
+            clock::now(&*self.config.clock),
+            &format!(
+                "Query failed because the connection to the database is likely dead: {}",
+                self.format_error(error)
+            ),
+            None,
+        );
+    }
 
-impl<C: Connection> LoggingConnection<C> {
-    pub fn new(conn: C, log_mode: DbLogMode) -> Self {
-        LoggingConnection { conn, log_mode }
+    /// Logs a query which failed because of a deadlock at `error` or because
+    /// of a lock timeout at `warn`, apart from the line of the failed query.
+    fn log_lock_error(
+        &self,
+        mode: DbLogMode,
+        query: &str,
+        start_time: clock::Timestamp,
+        error: &diesel::result::Error,
+    ) {
+        let lock_error = match lock_error::classify(error) {
+            Some(lock_error) if !mode.do_not_log() => lock_error,
+            _ => return,
+        };
+        let query = self.config.quoted(query);
+        self.config.emit_failure(
+            lock_error.level(),
+            mode,
+            start_time,
+            &format!("{} running: {}", lock_error.description(), query),
+            None,
+        );
     }
 
-    /// This is important becase it might be needed.
-    pub fn set_log_mode(&mut self, log_mode: DbLogMode) {
-        self.log_mode = log_mode;
+    /// Renders an error with the error formatter, or with its `Display` impl.
+    fn format_error(&self, error: &diesel::result::Error) -> String {
+        match &self.error_formatter {
+            Some(formatter) => formatter(error),
+            None => error.to_string(),
+        }
+    }
+
+    /// Logs a failed query at `error`.
+    fn log_failed(
+        &self,
+        mode: DbLogMode,
+        query: &str,
+        kind: QueryKind,
+        duration: Duration,
+        start_time: clock::Timestamp,
+        error: &diesel::result::Error,
+    ) {
+        if !self
+            .config
+            .failure_output()
+            .enabled(log::Level::Error, mode)
+        {
+            return;
+        }
+        let query = self.config.loggable(query);
+        let query = match self.config.max_bind_bytes {
+            Some(max_bytes) => Cow::Owned(binds::cap_binds(&query, max_bytes).into_owned()),
+            None => query,
+        };
+        let query = match self.config.max_query_bytes {
+            Some(max_bytes) => Cow::Owned(truncate_bytes(&query, max_bytes).into_owned()),
+            None => query,
+        };
+        let mut message = format!(
+            "Query failed after {} with error `{}`: {}",
+            format_duration_auto(duration, self.config.duration_precision),
+            self.format_error(error),
+            query
+        );
+        let correlation_id = correlation::effective_correlation_id();
+        if let Some(id) = &correlation_id {
+            message.insert_str(0, &format!("[req:{}] ", id));
+        }
+        let fields = LineFields {
+            query: &query,
+            kind,
+            duration,
+            wait: None,
+            rows: None,
+            slow: self.thresholds(mode).is_slow(duration),
+            raw: false,
+            statements: None,
+            correlation_id: correlation_id.as_deref(),
+        };
+        self.config
+            .emit_failure(log::Level::Error, mode, start_time, &message, Some(&fields));
+    }
+
+    /// Records the SQL of a failed query on the current `tracing` span, if enabled.
+    fn record_failure_on_span(&self, mode: DbLogMode, query: &str) {
+        if mode.do_not_log() || !self.config.records_failures_on_span() {
+            return;
+        }
+        let query = self.config.quoted(query);
+        // A no-op without a current span, or if it has no such field.
+        #[cfg(feature = "tracing-events")]
+        tracing::Span::current().record("db.statement", &*query);
+        #[cfg(not(feature = "tracing-events"))]
+        let _ = query;
+    }
+
+    /// Logs the line of a successful query in `Profile` mode.
+    fn log_profile(
+        &self,
+        shape: &str,
+        query_id: Option<u64>,
+        kind: QueryKind,
+        duration: Duration,
+        start_time: clock::Timestamp,
+        rows: Option<usize>,
+    ) {
+        if !self
+            .config
+            .output
+            .enabled(log::Level::Info, DbLogMode::Profile)
+        {
+            return;
+        }
+        let fp = fingerprint::fingerprint(shape);
+        let mut line = format!(
+            "fp={:016x} qid={:016x} kind={} duration_us={}",
+            fp,
+            query_id.unwrap_or(fp),
+            kind.as_str(),
+            duration.as_micros().min(u128::from(u64::MAX)) as u64
+        );
+        if let Some(rows) = rows {
+            line.push_str(&format!(" rows={}", rows));
+        }
+        let fields = LineFields {
+            query: "",
+            kind,
+            duration,
+            wait: None,
+            rows,
+            slow: self.thresholds(DbLogMode::Profile).is_slow(duration),
+            raw: false,
+            statements: None,
+            correlation_id: None,
+        };
+        self.config.emit(
+            log::Level::Info,
+            DbLogMode::Profile,
+            start_time,
+            &line,
+            Some(&fields),
+        );
+    }
+
+    /// Estimates the cost of a successful query which took at least the
+    /// configured duration, if it is at least the configured cost.
+    fn estimate_cost(&self, query: &str, duration: Duration, succeeded: bool) -> Option<f64> {
+        let estimate = self.estimate_cost?;
+        let (min_duration, min_cost) = self.config.cost_estimates?;
+        if !succeeded || duration < min_duration {
+            return None;
+        }
+        estimate(&self.conn, query).filter(|cost| *cost >= min_cost)
+    }
+
+    /// Reports a query which ran while the connection was instrumented.
+    /// `outcome` is the number of rows returned or affected, or the error.
+    #[allow(clippy::too_many_arguments)]
+    fn log_executed(
+        &self,
+        mode: DbLogMode,
+        query: &str,
+        kind: QueryKind,
+        static_id: Option<TypeId>,
+        raw: bool,
+        seq: u64,
+        started: std::time::Instant,
+        duration: Duration,
+        start_time: clock::Timestamp,
+        outcome: Result<Option<usize>, &diesel::result::Error>,
+    ) {
+        if let Err(error) = outcome {
+            self.record_failure_on_span(mode, query);
+            self.log_lock_error(mode, query, start_time, error);
+        }
+        if let (DbLogMode::OnError | DbLogMode::Profile, Err(error)) = (mode, outcome) {
+            self.log_failed(mode, query, kind, duration, start_time, error);
+        }
+        if !self.is_instrumented(mode) {
+            return;
+        }
+        let rows = outcome.ok().flatten();
+        let wait = self.take_pool_wait();
+        let result_bytes = self.result_bytes.swap(0, Ordering::Relaxed);
+        let on_slow_query = self.on_slow_query.as_ref();
+        let profile = mode == DbLogMode::Profile;
+        let needs_id = profile
+            || self.config.log_query_id
+            || self.events.is_open()
+            || self.event_buffer.is_enabled()
+            || !self.sinks.is_empty()
+            || on_slow_query.is_some()
+            || self.slow_classifier.is_some()
+            || self.async_slow_queries.is_open();
+        let needs_shape = self.adaptive.is_enabled()
+            || self.aggregates.is_enabled()
+            || self.slow_query_limiter.is_enabled()
+            || profile
+            || (needs_id && static_id.is_none());
+        let shape = if needs_shape {
+            normalize_sql(query, &self.config.normalization)
+        } else {
+            String::new()
+        };
+        let query_id = if needs_id {
+            Some(match static_id {
+                Some(id) => fingerprint::type_fingerprint(id),
+                None => fingerprint::fingerprint(&shape),
+            })
+        } else {
+            None
+        };
+        let baseline = self.adaptive.observe(&shape, duration);
+        self.aggregates.add(&shape, duration);
+        let mut slow = self.thresholds(mode).is_slow(duration) || baseline.is_some();
+        let needs_event = self.slow_classifier.is_some()
+            || self.events.is_open()
+            || self.event_buffer.is_enabled()
+            || !self.sinks.is_empty()
+            || on_slow_query.is_some()
+            || self.async_slow_queries.is_open();
+        let mut event = if needs_event {
+            Some(QueryEvent {
+                query: query.to_owned(),
+                kind,
+                query_id: query_id.unwrap_or_default(),
+                seq,
+                start_time: start_time.0,
+                started,
+                duration,
+                rows,
+                slow,
+                raw,
+            })
+        } else {
+            None
+        };
+        let classified = match (&self.slow_classifier, &mut event) {
+            (Some(classify), Some(event)) => {
+                event.slow = classify(event);
+                slow = event.slow;
+                Some(slow)
+            }
+            _ => None,
+        };
+        self.last_query_was_slow.store(slow, Ordering::Relaxed);
+        self.slowest_query.observe(query, duration, start_time);
+        if let Some((top, interval)) = self.config.worst_queries_digest {
+            let digest = self.worst_queries.observe(
+                top,
+                interval,
+                query,
+                seq,
+                duration,
+                start_time,
+                self.config.duration_precision,
+            );
+            if let Some(digest) = digest {
+                self.config
+                    .emit_message(log::Level::Info, mode, start_time, &digest);
+            }
+        }
+        let on_slow_query = on_slow_query.filter(|_| slow);
+        let send_async = slow && self.async_slow_queries.is_open();
+        if let Some(event) = event {
+            if let Some(callback) = on_slow_query {
+                callback(&event);
+            }
+            for sink in &self.sinks {
+                sink.on_query(&event);
+            }
+            if send_async {
+                self.async_slow_queries.send(event.clone());
+            }
+            if self.event_buffer.is_enabled() {
+                self.event_buffer.push(event.clone());
+            }
+            if self.events.is_open() {
+                self.events.send(event);
+            }
+        }
+        if !logs_every_query(mode) {
+            return;
+        }
+        if profile {
+            if outcome.is_ok() {
+                self.log_profile(&shape, query_id, kind, duration, start_time, rows);
+            }
+            return;
+        }
+        if slow {
+            let (admitted, suppressed) = self.slow_query_limiter.check(&shape);
+            if let Some(suppressed) = suppressed {
+                self.config.emit_message(
+                    log::Level::Warn,
+                    mode,
+                    clock::now(&*self.config.clock),
+                    &suppressed.message(),
+                );
+            }
+            if !admitted {
+                return;
+            }
+        } else if outcome.is_ok() && !self.config.is_sampled(seq) {
+            return;
+        }
+        let executed = ExecutedQuery {
+            query,
+            kind,
+            duration,
+            wait,
+            query_id: query_id.filter(|_| self.config.log_query_id),
+            seq: Some(seq).filter(|_| self.config.show_seq),
+            raw,
+            cost: self.estimate_cost(query, duration, outcome.is_ok()),
+            result_bytes: Some(result_bytes).filter(|_| self.config.estimate_result_bytes),
+            start_time,
+            rows,
+            baseline,
+            classified,
+            test_transaction: self.in_test_transaction.load(Ordering::Relaxed),
+        };
+        log_query(&executed, mode, &self.config);
+        if let Some(explain) = self.explain {
+            if self.thresholds(mode).is_very_slow(duration) {
+                if let Some(plan) = explain(&self.conn, query) {
+                    let fields = LineFields {
+                        query,
+                        kind,
+                        duration,
+                        wait: executed.wait,
+                        rows,
+                        slow: true,
+                        raw: executed.raw,
+                        statements: None,
+                        correlation_id: None,
+                    };
+                    self.config
+                        .emit(log::Level::Warn, mode, start_time, &plan, Some(&fields));
+                }
+            }
+        }
     }
 }
 
+/// The number of query shapes in the report of `LoggingConnection::report`.
+const REPORT_SIZE: usize = 10;
+
 impl<C> SimpleConnection for LoggingConnection<C>
 where
     C: Connection + Send + 'static,
 {
     fn batch_execute(&self, query: &str) -> QueryResult<()> {
-        self.conn.batch_execute(query)
+        self.maybe_log(
+            Access::Write,
+            |_| QueryKind::Batch,
+            None,
+            true,
+            || Cow::Borrowed(query),
+            || self.conn.batch_execute(query),
+            |_| None,
+        )
     }
 }
 
+// Every way diesel 1.4 runs a query ends up in one of these methods:
+// `load`, `get_result(s)` and `first` in `query_by_index`, `sql_query(..).load`
+// in `query_by_name`, `execute` on a query in `execute_returning_count` and
+// `Connection::execute` in `execute`. All of them go through `maybe_log`,
+// so a new method must too, and `tests/entry_points.rs` checks each of them. Diesel 1.4 has no streaming `load_iter`: rows are
+// collected into a `Vec` before these return, so the logged duration and row
+// count always cover the whole result.
 impl<C: Connection> Connection for LoggingConnection<C>
 where
     C: Connection + Send + 'static,
@@ -83,24 +2059,57 @@ where
     type TransactionManager = LoggingTransactionManager<C>;
 
     fn establish(database_url: &str) -> ConnectionResult<Self> {
-        let log_mode = DbLogMode::from_env();
-        let conn = C::establish(database_url)?;
-        Ok(LoggingConnection { conn, log_mode })
+        let log_mode = global_log_mode();
+        let config = config::defaults_for::<C::Backend>();
+        let time_utc = clock::now(&*config.clock);
+        let start_time = std::time::Instant::now();
+        let conn = C::establish(database_url);
+        let duration = format_duration_auto(start_time.elapsed(), config.duration_precision);
+        match conn {
+            Ok(conn) => {
+                if logs_every_query(log_mode) {
+                    config.emit_message(
+                        log::Level::Debug,
+                        log_mode,
+                        time_utc,
+                        &format!(
+                            "Connected to {} in {}",
+                            redact::redact_url(database_url),
+                            duration
+                        ),
+                    );
+                }
+                Ok(LoggingConnection::new(conn, log_mode))
+            }
+            Err(error) => {
+                if !log_mode.do_not_log() {
+                    config.emit_message(
+                        log::Level::Error,
+                        log_mode,
+                        time_utc,
+                        &format!(
+                            "Failed to connect to {} after {}: {}",
+                            redact::redact_url(database_url),
+                            duration,
+                            error
+                        ),
+                    );
+                }
+                Err(error)
+            }
+        }
     }
 
     fn execute(&self, query: &str) -> QueryResult<usize> {
-        if self.log_mode.do_not_log() {
-            self.conn.execute(query)
-        } else {
-            let time_utc = chrono::Utc::now();
-            let start_time = std::time::Instant::now();
-
-            let result = self.conn.execute(query);
-            let duration = start_time.elapsed();
-
-            log_query(query, duration, time_utc, self.log_mode);
-            result
-        }
+        self.maybe_log(
+            Access::Write,
+            QueryKind::classify,
+            None,
+            true,
+            || Cow::Borrowed(query),
+            || self.conn.execute(query),
+            |rows| Some(*rows),
+        )
     }
 
     fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
@@ -111,21 +2120,16 @@ where
         U: Queryable<T::SqlType, Self::Backend>,
     {
         let query = source.as_query();
-
-        if self.log_mode.do_not_log() {
-            self.conn.query_by_index(query)
-        } else {
-            let debug_query = diesel::debug_query::<Self::Backend, _>(&query).to_string();
-
-            let time_utc = chrono::Utc::now();
-            let start_time = std::time::Instant::now();
-
-            let result = self.conn.query_by_index(query);
-            let duration = start_time.elapsed();
-
-            log_query(&debug_query, duration, time_utc, self.log_mode);
-            result
-        }
+        // Run the query by reference, so it can still be rendered afterwards.
+        self.maybe_log(
+            Access::Read,
+            QueryKind::classify,
+            T::Query::query_id(),
+            false,
+            || Cow::Owned(diesel::debug_query::<Self::Backend, _>(&query).to_string()),
+            || self.conn.query_by_index(&query),
+            |rows| self.record_result_bytes(rows),
+        )
     }
 
     fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
@@ -133,39 +2137,30 @@ where
         T: QueryFragment<Self::Backend> + QueryId,
         U: QueryableByName<Self::Backend>,
     {
-        if self.log_mode.do_not_log() {
-            self.conn.query_by_name(source)
-        } else {
-            let debug_query = diesel::debug_query::<Self::Backend, _>(&source).to_string();
-
-            let time_utc = chrono::Utc::now();
-            let start_time = std::time::Instant::now();
-            let result = self.conn.query_by_name(source);
-            let duration = start_time.elapsed();
-
-            log_query(&debug_query, duration, time_utc, self.log_mode);
-            result
-        }
+        self.maybe_log(
+            Access::Read,
+            QueryKind::classify,
+            T::query_id(),
+            true,
+            || Cow::Owned(diesel::debug_query::<Self::Backend, _>(source).to_string()),
+            || self.conn.query_by_name(source),
+            |rows| self.record_result_bytes(rows),
+        )
     }
 
     fn execute_returning_count<T>(&self, source: &T) -> QueryResult<usize>
     where
         T: QueryFragment<Self::Backend> + QueryId,
     {
-        if self.log_mode.do_not_log() {
-            self.conn.execute_returning_count(source)
-        } else {
-            let debug_query = diesel::debug_query::<Self::Backend, _>(&source).to_string();
-
-            let time_utc = chrono::Utc::now();
-            let start_time = std::time::Instant::now();
-
-            let result = self.conn.execute_returning_count(source);
-            let duration = start_time.elapsed();
-
-            log_query(&debug_query, duration, time_utc, self.log_mode);
-            result
-        }
+        self.maybe_log(
+            Access::Write,
+            QueryKind::classify,
+            T::query_id(),
+            false,
+            || Cow::Owned(diesel::debug_query::<Self::Backend, _>(source).to_string()),
+            || self.conn.execute_returning_count(source),
+            |rows| Some(*rows),
+        )
     }
 
     fn transaction_manager(&self) -> &Self::TransactionManager {
@@ -179,10 +2174,123 @@ where
     }
 
     fn begin_test_transaction(&self) -> QueryResult<()> {
-        self.conn.begin_test_transaction()
+        self.conn.begin_test_transaction()?;
+        self.in_test_transaction.store(true, Ordering::Relaxed);
+        if !self.log_mode.do_not_log() {
+            emit_log!(log::Level::Debug, "Began test transaction");
+        }
+        Ok(())
+    }
+}
+
+impl<C> LoggingConnection<C>
+where
+    C: Connection + Send + 'static,
+    <C::Backend as Backend>::QueryBuilder: Default,
+{
+    /// Runs `f` inside a transaction which is always rolled back,
+    /// even if `f` panics. Panics if `f` returns an error.
+    ///
+    /// The begin and the rollback of the test transaction are logged at `debug`
+    /// unless logging is disabled.
+    pub fn test_transaction<R>(&self, f: impl FnOnce() -> QueryResult<R>) -> R {
+        self.transaction_manager()
+            .begin_transaction(self)
+            .unwrap_or_else(|e| panic!("Failed to begin the test transaction: {:?}", e));
+        self.in_test_transaction.store(true, Ordering::Relaxed);
+        if !self.log_mode.do_not_log() {
+            emit_log!(log::Level::Debug, "Began test transaction");
+        }
+        let _rollback = RollbackOnDrop(self);
+        f().unwrap_or_else(|e| panic!("Test transaction failed: {:?}", e))
+    }
+
+    /// Like `load`, but also returns how long the query took, so callers can
+    /// react to slow queries themselves. The duration is the one the query is
+    /// logged with, without the time needed to render and log it.
+    pub fn load_timed<T, U>(&self, source: T) -> QueryResult<(Vec<U>, Duration)>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<C::Backend> + QueryId,
+        C::Backend: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, C::Backend>,
+    {
+        let rows = self.query_by_index(source)?;
+        let nanos = self.last_query_nanos.load(Ordering::Relaxed);
+        Ok((rows, Duration::from_nanos(nanos)))
+    }
+
+    /// Returns the SQL of `source` with its binds, exactly as it would be logged
+    /// before truncation, without running it. Works regardless of the log mode.
+    pub fn render_query<T>(&self, source: &T) -> String
+    where
+        T: QueryFragment<C::Backend> + QueryId,
+    {
+        diesel::debug_query::<C::Backend, _>(source).to_string()
+    }
+}
+
+impl<C: Connection + 'static> LoggingConnection<C> {
+    /// Returns the connection as `&dyn Any`, for code which erased its type
+    /// and needs to get back to it, e.g. with [`LoggingConnection::downcast_ref`].
+    pub fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Recovers a `LoggingConnection<C>` from `&dyn Any`. This only succeeds
+    /// if the concrete connection type `C` is known and matches,
+    /// e.g. `LoggingConnection::<PgConnection>::downcast_ref(any)`.
+    pub fn downcast_ref(any: &dyn Any) -> Option<&Self> {
+        any.downcast_ref()
+    }
+}
+
+/// A view of a `LoggingConnection` which runs queries on the wrapped connection
+/// without logging them, returned by `LoggingConnection::no_log`. Diesel
+/// queries take it as `query.execute(&*conn.no_log())`.
+pub struct Unlogged<'a, C>(&'a C);
+
+impl<C> std::ops::Deref for Unlogged<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.0
+    }
+}
+
+/// Rolls back the open transaction of the connection when dropped.
+struct RollbackOnDrop<'a, C>(&'a LoggingConnection<C>)
+where
+    C: Connection + Send + 'static,
+    <C::Backend as Backend>::QueryBuilder: Default;
+
+impl<'a, C> Drop for RollbackOnDrop<'a, C>
+where
+    C: Connection + Send + 'static,
+    <C::Backend as Backend>::QueryBuilder: Default,
+{
+    fn drop(&mut self) {
+        let conn = self.0;
+        let result = conn.transaction_manager().rollback_transaction(conn);
+        conn.in_test_transaction.store(false, Ordering::Relaxed);
+        if !conn.log_mode.do_not_log() {
+            match result {
+                Ok(()) => emit_log!(log::Level::Debug, "Rolled back test transaction"),
+                Err(e) => emit_log!(
+                    log::Level::Warn,
+                    "Failed to roll back test transaction: {}",
+                    e
+                ),
+            }
+        }
     }
 }
 
+/// Delegates to the transaction manager of the wrapped connection.
+///
+/// This is the same for every backend: `PgConnection` and `MysqlConnection` both
+/// use diesel's `AnsiTransactionManager`, other connections bring their own
+/// manager, and none of them needs a backend specific impl here.
 #[repr(transparent)]
 pub struct LoggingTransactionManager<C: Connection> {
     inner: C::TransactionManager,
@@ -194,15 +2302,54 @@ where
     <C::Backend as Backend>::QueryBuilder: Default,
 {
     fn begin_transaction(&self, conn: &LoggingConnection<C>) -> QueryResult<()> {
-        self.inner.begin_transaction(&conn.conn)
+        let outermost = self.inner.get_transaction_depth() == 0;
+        let result = self.inner.begin_transaction(&conn.conn);
+        if result.is_ok() {
+            conn.transaction_buffer.begin();
+            conn.transaction_queries.begin();
+            conn.transaction_summaries.begin();
+            if outermost && conn.config.log_transaction_duration {
+                conn.transaction_timer.start();
+            }
+        }
+        result
     }
 
     fn rollback_transaction(&self, conn: &LoggingConnection<C>) -> QueryResult<()> {
-        self.inner.rollback_transaction(&conn.conn)
+        let result = self.inner.rollback_transaction(&conn.conn);
+        // Dump the statements even if the rollback itself failed,
+        // the transaction is broken either way.
+        conn.transaction_queries.rollback();
+        conn.transaction_summaries.rollback();
+        if let Some(buffered) = conn.transaction_buffer.rollback() {
+            transaction::log_rolled_back(&buffered);
+        }
+        if self.inner.get_transaction_depth() == 0 {
+            conn.log_transaction_end("rolled back");
+        }
+        result
     }
 
     fn commit_transaction(&self, conn: &LoggingConnection<C>) -> QueryResult<()> {
-        self.inner.commit_transaction(&conn.conn)
+        let time_utc = clock::now(&*conn.config.clock);
+        let start_time = std::time::Instant::now();
+        let result = self.inner.commit_transaction(&conn.conn);
+        let duration = start_time.elapsed();
+        if result.is_ok() {
+            conn.transaction_buffer.commit();
+            conn.transaction_queries.commit();
+            if let Some(summary) = conn.transaction_summaries.commit() {
+                if !conn.log_mode.do_not_log() {
+                    conn.config
+                        .emit_message(log::Level::Info, conn.log_mode, time_utc, &summary);
+                }
+            }
+            conn.log_commit(duration, time_utc);
+            if self.inner.get_transaction_depth() == 0 {
+                conn.log_transaction_end("committed");
+            }
+        }
+        result
     }
 
     fn get_transaction_depth(&self) -> u32 {
@@ -210,80 +2357,361 @@ where
     }
 }
 
-/// This function now takes a `chrono::DateTime` for logging in `ExcessiveMode`, which uses `println`
-/// and can be accomplished even when general `gst-server` logging is disabled.
-/// Also the `DbLogMode` determines the type of logging.
-fn log_query(
-    query: &str,
+/// Returns true if every query run in `mode` is logged.
+fn logs_every_query(mode: DbLogMode) -> bool {
+    !mode.do_not_log() && mode != DbLogMode::OnError
+}
+
+/// Everything known about a query which ran, for logging it.
+#[derive(Clone, Copy)]
+struct ExecutedQuery<'a> {
+    query: &'a str,
+    kind: QueryKind,
     duration: Duration,
-    start_time: chrono::DateTime<chrono::Utc>,
-    db_log_mode: DbLogMode,
-) {
-    use std::borrow::Cow;
+    /// The time spent waiting for a pooled connection, if it was recorded.
+    wait: Option<Duration>,
+    /// The identity of the query, if it is logged.
+    query_id: Option<u64>,
+    /// The sequence number of the query on its connection, if it is logged.
+    seq: Option<u64>,
+    /// Whether the query is hand-written SQL.
+    raw: bool,
+    /// The estimated cost of the query, if it is logged.
+    cost: Option<f64>,
+    /// The estimated size of the returned rows, if it is logged.
+    result_bytes: Option<usize>,
+    start_time: clock::Timestamp,
+    /// The number of rows returned or affected, if the query succeeded.
+    rows: Option<usize>,
+    /// The adaptive limit the query exceeded, if any.
+    baseline: Option<Duration>,
+    /// Whether the slow query classifier judged the query slow, if one is set.
+    classified: Option<bool>,
+    /// Whether the query ran inside a test transaction.
+    test_transaction: bool,
+}
 
-    // SAN check.
-    debug_assert!(!db_log_mode.do_not_log());
+/// Logs an executed query through the output of `config`. `db_log_mode` and
+/// its thresholds decide the level of the line from the duration of the
+/// query. Queries which started outside of the active window are not logged.
+fn log_query(executed: &ExecutedQuery<'_>, db_log_mode: DbLogMode, config: &LoggingConfig) {
+    use log::Level;
 
-    // Make query string.
-    let query = if db_log_mode != DbLogMode::ExcessiveMini {
-        Cow::Borrowed(query)
+    let ExecutedQuery {
+        query,
+        kind,
+        duration,
+        wait,
+        query_id,
+        seq,
+        raw,
+        cost,
+        result_bytes,
+        start_time,
+        rows,
+        baseline,
+        classified,
+        test_transaction,
+    } = *executed;
+    if !config.is_in_active_window(start_time) {
+        return;
+    }
+    let query = &*config.loggable(query);
+    let correlation_id = correlation::effective_correlation_id();
+
+    // Queries above the fixed thresholds are reported as plain slow queries.
+    let thresholds = config.thresholds.get(db_log_mode);
+    let slow = classified.unwrap_or_else(|| thresholds.is_slow(duration));
+    let baseline = baseline.filter(|_| !slow);
+
+    // A classifier overrides the thresholds, except for telling very slow
+    // queries apart in `Standard` mode.
+    let level = match (classified, db_log_mode) {
+        (Some(false), DbLogMode::Standard | DbLogMode::Excessive | DbLogMode::ExcessiveMini) => {
+            Some(Level::Debug)
+        }
+        (Some(true), DbLogMode::Standard) if !thresholds.is_very_slow(duration) => {
+            Some(Level::Info)
+        }
+        (Some(true), DbLogMode::Excessive | DbLogMode::ExcessiveMini) => Some(Level::Warn),
+        _ => db_log_mode.should_emit(duration, &thresholds),
+    };
+    // `maybe_log` does not get here in modes which log no successful queries,
+    // but be safe.
+    let level = match level {
+        Some(level) => level,
+        None => return,
+    };
+    // Queries far above the threshold of `warn` are escalated to `error`.
+    let warn_threshold = if db_log_mode == DbLogMode::Standard {
+        thresholds.very_slow
     } else {
-        Cow::Owned(query.chars().take(40).collect::<String>())
+        thresholds.slow
+    };
+    let critical = config
+        .critical_multiplier
+        .and_then(|multiplier| warn_threshold.checked_mul(multiplier));
+    let level = match (db_log_mode, level) {
+        (DbLogMode::Verbose, level) => level,
+        (_, Level::Warn) if matches!(critical, Some(critical) if duration >= critical) => {
+            Level::Error
+        }
+        (_, Level::Debug) if baseline.is_some() => Level::Info,
+        (DbLogMode::Standard, Level::Warn) => config.levels.very_slow,
+        (DbLogMode::Standard, Level::Info) => config.levels.slow,
+        (DbLogMode::Standard, Level::Debug) => config.levels.normal,
+        (_, level) => level,
     };
+    if let Some(budget) = config
+        .timeout_budget
+        .filter(|_| config.is_near_timeout(duration))
+    {
+        let query = config.quoted(query);
+        config.emit_message(
+            Level::Warn,
+            db_log_mode,
+            start_time,
+            &format!(
+                "Query ran in {}, close to its timeout of {}: {}",
+                format_duration_auto(duration, config.duration_precision),
+                format_duration_auto(budget, config.duration_precision),
+                query
+            ),
+        );
+    }
+    if !config.output.enabled(level, db_log_mode) {
+        return;
+    }
 
-    match db_log_mode {
-        DbLogMode::Standard => {
-            if duration.as_secs() >= 5 {
-                log::warn!(
-                    "Slow query ran in {:.2} seconds: {}",
-                    duration_to_secs(duration),
-                    query
-                );
-            } else if duration.as_secs() >= 1 {
-                log::info!(
-                    "Slow query ran in {:.2} seconds: {}",
-                    duration_to_secs(duration),
-                    query
-                );
-            } else {
-                log::debug!("Query ran in {:.1} ms: {}", duration_to_ms(duration), query);
-            }
+    let statements = if kind == QueryKind::Batch {
+        Some(batch::split_statements(query).len())
+    } else {
+        None
+    };
+    let params = if config.show_param_count {
+        binds::bind_count(query)
+    } else {
+        None
+    };
+    let columns = if config.log_columns && kind == QueryKind::Select {
+        columns::selected_columns(query).map(|columns| columns.join(","))
+    } else {
+        None
+    };
+
+    // Make query string.
+    let query = match config.max_bind_bytes {
+        Some(max_bytes) => binds::cap_binds(query, max_bytes),
+        None => Cow::Borrowed(query),
+    };
+    let summarized =
+        matches!(config.summarize_query_bytes, Some(max_bytes) if query.len() > max_bytes);
+    let query = if summarized {
+        Cow::Owned(summarize(&query, kind, rows))
+    } else {
+        query
+    };
+    // A summary has no comments and is not runnable.
+    let query = if config.strip_comments && !summarized {
+        match comments::strip_comments(&query) {
+            Cow::Owned(stripped) => Cow::Owned(stripped),
+            Cow::Borrowed(_) => query,
         }
-        DbLogMode::Verbose => {
-            if duration.as_secs() >= 1 {
-                log::warn!(
-                    "Slow query ran in {:.2} seconds: {}",
-                    duration_to_secs(duration),
-                    query
-                );
-            } else {
-                log::warn!("Query ran in {:.1} ms: {}", duration_to_ms(duration), query);
-            }
+    } else {
+        query
+    };
+    let query = if config.psql_ready && !summarized {
+        Cow::Owned(psql::psql_ready(&query))
+    } else {
+        query
+    };
+    // Shorten last, so the short form is always cut from the cleaned query.
+    let query = if db_log_mode != DbLogMode::ExcessiveMini {
+        query
+    } else {
+        Cow::Owned(truncate_chars(&query, 40).into_owned())
+    };
+    let query = match config.max_query_bytes {
+        Some(max_bytes) if query.len() > max_bytes => {
+            Cow::Owned(truncate_bytes(&query, max_bytes).into_owned())
         }
-        DbLogMode::Excessive | DbLogMode::ExcessiveMini => {
-            if duration.as_secs() >= 1 {
-                println!(
-                    "[{}]: Slow query ran in {:.2} seconds: {}",
-                    start_time,
-                    duration_to_secs(duration),
-                    query
-                );
-            } else {
-                println!(
-                    "[{}]: Query ran in {:.1} ms: {}",
-                    start_time,
-                    duration_to_ms(duration),
-                    query
-                );
-            }
+        _ => query,
+    };
+
+    let fields = LineFields {
+        query: &query,
+        kind,
+        duration: round_to_bucket(duration, config.duration_bucket),
+        wait,
+        rows,
+        slow: slow || baseline.is_some(),
+        raw,
+        statements,
+        correlation_id: correlation_id.as_deref(),
+    };
+    let mut message = match config.format {
+        LogFormat::Text if config.append_machine_fields => {
+            let mut message = text_line(&fields, baseline, test_transaction, config, start_time);
+            format::append_machine_fields(&mut message, &fields);
+            message
         }
-        DbLogMode::NoLog => unreachable!("NoLog mode active. Should not be loggin."),
+        LogFormat::Text => text_line(&fields, baseline, test_transaction, config, start_time),
+        LogFormat::Logfmt => format::logfmt(&fields, baseline, test_transaction),
+    };
+    if let Some(columns) = columns {
+        format::append_columns(&mut message, config.format, &columns);
+    }
+    if let Some(params) = params {
+        format::append_param_count(&mut message, params);
+    }
+    if let Some(bytes) = result_bytes.filter(|_| fields.slow) {
+        format::append_result_bytes(&mut message, config.format, bytes);
+    }
+    if let Some(query_id) = query_id {
+        message.push_str(&format!(" qid={:016x}", query_id));
+    }
+    format::append_context(&mut message, config.format, &config.context);
+    if let Some(cost) = cost {
+        format::add_cost(&mut message, config.format, cost);
+    }
+    if fields.raw && (config.mark_raw_queries || config.format == LogFormat::Logfmt) {
+        format::mark_raw(&mut message, config.format);
+    }
+    if let Some(seq) = seq {
+        format::add_seq(&mut message, config.format, seq);
+    }
+    config.emit(level, db_log_mode, start_time, &message, Some(&fields));
+}
+
+/// Formats the human readable line of a query.
+/// It starts with the kind of the query, e.g. `[SELECT]`, and for inserts with
+/// the number of inserted rows, e.g. `[INSERT batch_rows=50]`.
+fn text_line(
+    fields: &LineFields<'_>,
+    baseline: Option<Duration>,
+    test_transaction: bool,
+    config: &LoggingConfig,
+    start_time: clock::Timestamp,
+) -> String {
+    let LineFields {
+        query,
+        kind,
+        duration,
+        wait,
+        rows,
+        slow,
+        ..
+    } = *fields;
+    let precision = config.duration_precision;
+    let ran = match wait {
+        Some(wait) => format!(
+            "waited {} for a connection, ran in {}",
+            format_duration_auto(wait, precision),
+            format_duration_auto(duration, precision)
+        ),
+        None => format!("ran in {}", format_duration_auto(duration, precision)),
+    };
+    let mut message = match baseline {
+        Some(baseline) => format!(
+            "Unusually slow query {} (baseline {}): {}",
+            ran,
+            format_duration_auto(baseline, precision),
+            query
+        ),
+        None if slow => match &config.slow_query_template {
+            Some(template) => format::fill_template(
+                template,
+                &format_duration_auto(duration, precision),
+                rows,
+                start_time,
+                query,
+            ),
+            None => format!("Slow query {}: {}", ran, query),
+        },
+        None => format!("Query {}: {}", ran, query),
+    };
+    let kind = kind.as_str().to_ascii_uppercase();
+    let prefix = match (fields.batch_rows(), fields.statements) {
+        (Some(batch_rows), _) => format!("[{} batch_rows={}] ", kind, batch_rows),
+        (None, Some(statements)) => format!("[{} statements={}] ", kind, statements),
+        (None, None) => format!("[{}] ", kind),
+    };
+    message.insert_str(0, &prefix);
+    if test_transaction {
+        message.insert_str(0, "[test-tx] ");
+    }
+    if let Some(id) = fields.correlation_id {
+        message.insert_str(0, &format!("[req:{}] ", id));
+    }
+    message
+}
+
+/// Describes a query by its kind, size and row count instead of its SQL.
+fn summarize(query: &str, kind: QueryKind, rows: Option<usize>) -> String {
+    let kind = match kind {
+        QueryKind::Insert => Cow::Borrowed("bulk insert"),
+        QueryKind::Batch => Cow::Owned(format!(
+            "batch of {} statements",
+            batch::split_statements(query).len()
+        )),
+        kind => Cow::Borrowed(kind.as_str()),
+    };
+    let size = if query.len() < 1024 {
+        format!("{} B", query.len())
+    } else {
+        format!("{} KB", query.len() / 1024)
+    };
+    match rows {
+        Some(rows) => format!("<{}, {} SQL, {} rows>", kind, size, rows),
+        None => format!("<{}, {} SQL>", kind, size),
     }
 }
 
+/// Rounds `duration` to the nearest multiple of `bucket`, if set.
+fn round_to_bucket(duration: Duration, bucket: Option<Duration>) -> Duration {
+    let bucket = match bucket.map(|bucket| bucket.as_nanos()) {
+        Some(bucket) if bucket > 0 => bucket,
+        _ => return duration,
+    };
+    let rounded = (duration.as_nanos() + bucket / 2) / bucket * bucket;
+    Duration::from_nanos(rounded.min(u128::from(u64::MAX)) as u64)
+}
+
 const NANOS_PER_MILLI: u32 = 1_000_000;
 const MILLIS_PER_SEC: u32 = 1_000;
 
+/// Renders a duration in the most legible unit: microseconds below one
+/// millisecond, milliseconds below one second and seconds otherwise.
+/// A query which took less than the precision shows e.g. `<1 µs`, not `0 µs`,
+/// so it never looks like it did not run.
+pub(crate) fn format_duration_auto(duration: Duration, precision: Option<usize>) -> String {
+    if duration < Duration::from_millis(1) {
+        let ran = duration > Duration::from_secs(0);
+        match precision {
+            Some(precision) => {
+                let micros = format!("{:.*}", precision, duration.subsec_nanos() as f32 / 1_000.0);
+                // Decided on the rounded digits, which may round half down.
+                if ran && micros.chars().all(|c| c == '0' || c == '.') {
+                    let smallest = 10f32.powi(-(precision.min(9) as i32));
+                    format!("<{:.*} µs", precision, smallest)
+                } else {
+                    format!("{} µs", micros)
+                }
+            }
+            None if ran && duration.as_micros() == 0 => String::from("<1 µs"),
+            None => format!("{} µs", duration.as_micros()),
+        }
+    } else if duration < Duration::from_secs(1) {
+        format!("{:.*} ms", precision.unwrap_or(1), duration_to_ms(duration))
+    } else {
+        format!(
+            "{:.*} s",
+            precision.unwrap_or(2),
+            duration_to_secs(duration)
+        )
+    }
+}
+
 fn duration_to_secs(duration: Duration) -> f32 {
     duration_to_ms(duration) / MILLIS_PER_SEC as f32
 }
@@ -293,6 +2721,9 @@ fn duration_to_ms(duration: Duration) -> f32 {
         + (duration.subsec_nanos() as f32 / NANOS_PER_MILLI as f32)
 }
 
+/// Lets `ConnectionManager<LoggingConnection<C>>` pool logging connections of
+/// every backend which supports r2d2. Pings go to the inner connection, so they
+/// are not logged.
 impl<C> diesel::r2d2::R2D2Connection for LoggingConnection<C>
 where
     C: diesel::r2d2::R2D2Connection,
@@ -313,13 +2744,146 @@ where
     }
 }
 
+/// Delegates to the wrapped connection, so each backend keeps its own strategy,
+/// e.g. a single `UPDATE ... RETURNING` on Postgres, or an `UPDATE` followed by
+/// a `SELECT` on SQLite. Those statements run on the wrapped connection, so
+/// the round trip is logged as one query with the SQL of its `UPDATE`, without
+/// the `RETURNING` clause of Postgres or the `SELECT` of SQLite, and without a
+/// row count. The bounds are the ones every backend of diesel requires.
 impl<Changes, Output, C> diesel::query_dsl::UpdateAndFetchResults<Changes, Output>
     for LoggingConnection<C>
 where
     C: diesel::query_dsl::UpdateAndFetchResults<Changes, Output>,
     Self: Connection,
+    <C::Backend as Backend>::QueryBuilder: Default,
+    Changes: Copy
+        + diesel::query_builder::AsChangeset<
+            Target = <Changes as diesel::associations::HasTable>::Table,
+        > + diesel::query_builder::IntoUpdateTarget,
+    diesel::dsl::Update<Changes, Changes>: QueryFragment<C::Backend>,
 {
     fn update_and_fetch(&self, changeset: Changes) -> QueryResult<Output> {
-        self.conn.update_and_fetch(changeset)
+        self.maybe_log(
+            Access::Write,
+            QueryKind::classify,
+            None,
+            false,
+            || {
+                let update = diesel::update(changeset).set(changeset);
+                Cow::Owned(diesel::debug_query::<C::Backend, _>(&update).to_string())
+            },
+            || self.conn.update_and_fetch(changeset),
+            |_| None,
+        )
+    }
+}
+
+// The logging state of a connection is `Sync` on its own, whatever the inner
+// connection is, so it never adds a data race to a connection shared between threads.
+const _: fn() = || {
+    fn assert_sync<T: Sync>() {}
+
+    assert_sync::<DbLogMode>();
+    assert_sync::<LoggingConfig>();
+    assert_sync::<AdaptiveBaselines>();
+    assert_sync::<QueryAggregates>();
+    assert_sync::<SlowestQuery>();
+    assert_sync::<EventChannel>();
+    assert_sync::<Vec<Arc<dyn QuerySink>>>();
+    assert_sync::<SlowQueryCallback>();
+    assert_sync::<AsyncSlowQueries>();
+    assert_sync::<CommitCallback>();
+    assert_sync::<ErrorFormatter>();
+    assert_sync::<LogPredicate>();
+    assert_sync::<SlowQueryLimiter>();
+    assert_sync::<TransactionBuffer>();
+    assert_sync::<capture::QueryCapture>();
+    assert_sync::<scope::Scope>();
+    assert_sync::<TotalTime>();
+    assert_sync::<HealthWindow>();
+    assert_sync::<TransactionQueryCounter>();
+    assert_sync::<TransactionTimer>();
+    assert_sync::<TransactionSummaries>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_shown_in_the_most_legible_unit() {
+        let auto = |duration| format_duration_auto(duration, None);
+        assert_eq!(auto(Duration::from_micros(999)), "999 µs");
+        assert_eq!(auto(Duration::from_micros(1_500)), "1.5 ms");
+        assert_eq!(auto(Duration::from_millis(999)), "999.0 ms");
+        assert_eq!(auto(Duration::from_millis(2_250)), "2.25 s");
+        assert_eq!(auto(Duration::from_secs(90)), "90.00 s");
+    }
+
+    #[test]
+    fn the_precision_applies_to_every_unit() {
+        let precise = |duration| format_duration_auto(duration, Some(3));
+        assert_eq!(precise(Duration::from_nanos(12_345)), "12.345 µs");
+        assert_eq!(precise(Duration::from_micros(1_500)), "1.500 ms");
+        assert_eq!(precise(Duration::from_millis(2_250)), "2.250 s");
+    }
+
+    #[test]
+    fn queries_faster_than_the_precision_do_not_show_zero() {
+        assert_eq!(
+            format_duration_auto(Duration::from_nanos(400), None),
+            "<1 µs"
+        );
+        assert_eq!(format_duration_auto(Duration::from_nanos(0), None), "0 µs");
+        let precise =
+            |nanos, precision| format_duration_auto(Duration::from_nanos(nanos), Some(precision));
+        assert_eq!(precise(4, 2), "<0.01 µs");
+        assert_eq!(precise(5, 2), "<0.01 µs");
+        assert_eq!(precise(6, 2), "0.01 µs");
+        assert_eq!(precise(0, 2), "0.00 µs");
+        assert_eq!(precise(400, 0), "<1 µs");
+    }
+
+    const MODES: [DbLogMode; 8] = [
+        DbLogMode::NoLog,
+        DbLogMode::Standard,
+        DbLogMode::Verbose,
+        DbLogMode::Excessive,
+        DbLogMode::ExcessiveMini,
+        DbLogMode::OnError,
+        DbLogMode::Silent,
+        DbLogMode::Profile,
+    ];
+
+    #[test]
+    fn log_modes_survive_being_stored_as_u8() {
+        for &mode in &MODES {
+            assert_eq!(DbLogMode::from_u8(mode.to_u8()), Some(mode));
+        }
+        assert_eq!(DbLogMode::from_u8(MODES.len() as u8), None);
+        assert_eq!(DbLogMode::from_u8(GLOBAL_LOG_MODE_UNSET), None);
+    }
+
+    #[test]
+    fn every_mode_decides_the_level_of_a_query() {
+        use log::Level;
+
+        let thresholds = SlowQueryThresholds {
+            slow: Duration::from_secs(1),
+            very_slow: Duration::from_secs(5),
+        };
+        let levels = |mode: DbLogMode| {
+            [0, 1, 5].map(|secs| mode.should_emit(Duration::from_secs(secs), &thresholds))
+        };
+        let debug_info_warn = [Some(Level::Debug), Some(Level::Info), Some(Level::Warn)];
+        assert_eq!(levels(DbLogMode::Standard), debug_info_warn);
+        assert_eq!(levels(DbLogMode::Verbose), [Some(Level::Warn); 3]);
+        let debug_warn_warn = [Some(Level::Debug), Some(Level::Warn), Some(Level::Warn)];
+        assert_eq!(levels(DbLogMode::Excessive), debug_warn_warn);
+        assert_eq!(levels(DbLogMode::ExcessiveMini), debug_warn_warn);
+        assert_eq!(levels(DbLogMode::Profile), [Some(Level::Info); 3]);
+        for &mode in &[DbLogMode::NoLog, DbLogMode::OnError, DbLogMode::Silent] {
+            assert_eq!(levels(mode), [None; 3]);
+        }
     }
 }
@@ -0,0 +1,47 @@
+use diesel::result::Error;
+
+/// A query which failed because of lock contention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LockError {
+    /// SQLSTATE 40P01 in Postgres, 1213 in MySQL.
+    Deadlock,
+    /// SQLSTATE 55P03 in Postgres, 1205 in MySQL.
+    LockTimeout,
+}
+
+impl LockError {
+    /// Deadlocks are logged at `error`, lock timeouts at `warn`.
+    pub(crate) fn level(self) -> log::Level {
+        match self {
+            LockError::Deadlock => log::Level::Error,
+            LockError::LockTimeout => log::Level::Warn,
+        }
+    }
+
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            LockError::Deadlock => "Deadlock detected",
+            LockError::LockTimeout => "Lock timeout",
+        }
+    }
+}
+
+/// Classifies lock contention errors. Diesel 1.4 does not expose the SQLSTATE
+/// of a `DatabaseError`, so the messages Postgres and MySQL use for these
+/// codes are matched instead.
+pub(crate) fn classify(error: &Error) -> Option<LockError> {
+    let message = match error {
+        Error::DatabaseError(_, info) => info.message().to_ascii_lowercase(),
+        _ => return None,
+    };
+    if message.contains("deadlock detected") || message.contains("deadlock found") {
+        Some(LockError::Deadlock)
+    } else if message.contains("lock timeout")
+        || message.contains("could not obtain lock")
+        || message.contains("lock wait timeout exceeded")
+    {
+        Some(LockError::LockTimeout)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,53 @@
+/// Emits an event with a `log::Level` picked at runtime through `tracing`,
+/// whose macros only take constant levels.
+#[cfg(feature = "tracing-events")]
+macro_rules! tracing_event {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            log::Level::Error => tracing::event!(tracing::Level::ERROR, $($arg)+),
+            log::Level::Warn => tracing::event!(tracing::Level::WARN, $($arg)+),
+            log::Level::Info => tracing::event!(tracing::Level::INFO, $($arg)+),
+            log::Level::Debug => tracing::event!(tracing::Level::DEBUG, $($arg)+),
+            log::Level::Trace => tracing::event!(tracing::Level::TRACE, $($arg)+),
+        }
+    };
+}
+
+/// Logs a line through `log`, or through `tracing` with the `tracing-events`
+/// feature, so there is exactly one emission path.
+macro_rules! emit_log {
+    ($level:expr, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing-events")]
+        tracing_event!($level, $($arg)+);
+        #[cfg(not(feature = "tracing-events"))]
+        log::log!($level, $($arg)+);
+    }};
+}
+
+/// Returns true if a line with this level would be emitted by `emit_log!`.
+/// `tracing` has no targets picked at runtime, so they only count for `log`.
+macro_rules! log_enabled {
+    (target: $target:expr, $level:expr) => {{
+        #[cfg(feature = "tracing-events")]
+        let enabled = {
+            let _ = $target;
+            log_enabled!($level)
+        };
+        #[cfg(not(feature = "tracing-events"))]
+        let enabled = log::log_enabled!(target: $target, $level);
+        enabled
+    }};
+    ($level:expr) => {{
+        #[cfg(feature = "tracing-events")]
+        let enabled = match $level {
+            log::Level::Error => tracing::enabled!(tracing::Level::ERROR),
+            log::Level::Warn => tracing::enabled!(tracing::Level::WARN),
+            log::Level::Info => tracing::enabled!(tracing::Level::INFO),
+            log::Level::Debug => tracing::enabled!(tracing::Level::DEBUG),
+            log::Level::Trace => tracing::enabled!(tracing::Level::TRACE),
+        };
+        #[cfg(not(feature = "tracing-events"))]
+        let enabled = log::log_enabled!($level);
+        enabled
+    }};
+}
@@ -0,0 +1,75 @@
+/// Whether an `UPDATE` or `DELETE` has no `WHERE` clause of its own, so it
+/// changes every row of its table. A `WHERE` in a comment, a string literal,
+/// a quoted identifier or a subquery does not count.
+pub(crate) fn lacks_where(query: &str) -> bool {
+    let stripped = crate::comments::strip_comments(query);
+    let sql = match stripped.find(" -- binds: ") {
+        Some(pos) => &stripped[..pos],
+        None => &stripped[..],
+    };
+    let bytes = sql.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => {
+                // A doubled quote inside the literal just closes and reopens it.
+                i = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| b == quote)
+                    .map_or(bytes.len(), |pos| i + pos + 2);
+            }
+            b'(' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+            }
+            b if depth == 0 && is_identifier_byte(b) => {
+                let end = bytes[i..]
+                    .iter()
+                    .position(|&b| !is_identifier_byte(b))
+                    .map_or(bytes.len(), |pos| i + pos);
+                if sql[i..end].eq_ignore_ascii_case("WHERE") {
+                    return false;
+                }
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    true
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_with_a_where_clause_are_recognized() {
+        assert!(!lacks_where("UPDATE t SET a = 1 WHERE id = 2"));
+        assert!(!lacks_where("delete from t where id = 2"));
+        assert!(!lacks_where(
+            "UPDATE \"t\" SET \"a\" = $1 WHERE \"t\".\"id\" = $2 -- binds: [1, 2]"
+        ));
+    }
+
+    #[test]
+    fn where_outside_of_the_statement_itself_does_not_count() {
+        assert!(lacks_where("DELETE FROM t"));
+        assert!(lacks_where("UPDATE t SET a = 'WHERE'"));
+        assert!(lacks_where("UPDATE t SET a = 'it''s' /* WHERE */"));
+        assert!(lacks_where("UPDATE \"where\" SET a = 1"));
+        assert!(lacks_where(
+            "UPDATE t SET a = (SELECT b FROM u WHERE u.id = 1)"
+        ));
+        assert!(lacks_where("DELETE FROM t -- binds: [\"WHERE\"]"));
+        assert!(lacks_where("UPDATE t SET nowhere = 1"));
+    }
+}
@@ -0,0 +1,384 @@
+use crate::comments::strip_comments;
+
+/// Controls how queries are normalized into the shapes which group executions
+/// for aggregates, adaptive thresholds and rate limiting.
+///
+/// The `-- binds: [...]` trailer `debug_query` appends is always dropped and
+/// whitespace is always collapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizationConfig {
+    /// Remove `--` and `/* */` comments.
+    pub strip_comments: bool,
+    /// Replace lists of literals and placeholders, e.g. `IN (1, 2, 3)`, with `IN (?)`.
+    pub collapse_in_lists: bool,
+    /// Replace string and numeric literals with `?`, including the sign of
+    /// negative numbers, e.g. `x = -5` with `x = ?`.
+    pub parameterize_literals: bool,
+    /// Lowercase everything outside of string literals and quoted identifiers.
+    pub lowercase_keywords: bool,
+    /// Replace literals with numbered placeholders like `$3` instead of `?`,
+    /// continuing after the highest bind parameter of the query, as Postgres does.
+    pub numbered_placeholders: bool,
+}
+
+impl Default for NormalizationConfig {
+    fn default() -> Self {
+        NormalizationConfig {
+            strip_comments: false,
+            collapse_in_lists: false,
+            parameterize_literals: true,
+            lowercase_keywords: false,
+            numbered_placeholders: false,
+        }
+    }
+}
+
+impl NormalizationConfig {
+    /// Normalizes queries into the form of `pg_stat_statements.query`, e.g.
+    /// `INSERT INTO users (id, name) VALUES ($1, $2)`, so logged shapes can be
+    /// joined with the statistics of Postgres.
+    ///
+    /// This approximates the normalization of Postgres, which works on its parse
+    /// tree: it keeps the text of the first execution of a statement, so the
+    /// whitespace and comments may differ, and constants which Postgres keeps,
+    /// e.g. in `LIMIT` of some versions or type casts, are still replaced.
+    pub fn pg_stat_statements() -> Self {
+        NormalizationConfig {
+            strip_comments: false,
+            collapse_in_lists: false,
+            parameterize_literals: true,
+            lowercase_keywords: false,
+            numbered_placeholders: true,
+        }
+    }
+}
+
+/// Normalizes the SQL of a query as configured, so that executions which only
+/// differ in the normalized parts end up with the same shape.
+pub fn normalize_sql(sql: &str, config: &NormalizationConfig) -> String {
+    let sql = match sql.find(" -- binds: ") {
+        Some(pos) => &sql[..pos],
+        None => sql,
+    };
+    let stripped;
+    let sql = if config.strip_comments {
+        stripped = strip_comments(sql);
+        &*stripped
+    } else {
+        sql
+    };
+
+    let mut placeholders = if config.numbered_placeholders {
+        max_bind_parameter(sql)
+    } else {
+        0
+    };
+    let mut placeholder = || {
+        if config.numbered_placeholders {
+            placeholders += 1;
+            format!("${}", placeholders)
+        } else {
+            String::from("?")
+        }
+    };
+
+    let mut shape = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev = ' ';
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // `''` is an escaped quote inside the literal.
+            let mut literal = String::from(c);
+            while let Some(c) = chars.next() {
+                literal.push(c);
+                if c == '\'' {
+                    if chars.peek() == Some(&'\'') {
+                        literal.push('\'');
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            if config.parameterize_literals {
+                shape.push_str(&placeholder());
+                prev = '?';
+            } else {
+                shape.push_str(&literal);
+                prev = '\'';
+            }
+        } else if c == '"' {
+            // Quoted identifiers are case sensitive and may contain anything.
+            shape.push(c);
+            for c in chars.by_ref() {
+                shape.push(c);
+                if c == '"' {
+                    break;
+                }
+            }
+            prev = '"';
+        } else if config.parameterize_literals
+            && (c.is_ascii_digit()
+                || (c == '-' || c == '+')
+                    && matches!(chars.peek(), Some(c) if c.is_ascii_digit())
+                    && is_operand_position(&shape))
+            && !is_identifier_char(prev)
+            && prev != '$'
+        {
+            // The sign of a negative literal is part of it, so `x = -5` and
+            // `x = 5` share a shape, unlike the operator in `x - 5`.
+            while chars.next_if(|c| c.is_ascii_digit() || *c == '.').is_some() {}
+            shape.push_str(&placeholder());
+            prev = '?';
+        } else if c.is_whitespace() {
+            if prev != ' ' {
+                shape.push(' ');
+            }
+            prev = ' ';
+        } else if config.lowercase_keywords {
+            shape.extend(c.to_lowercase());
+            prev = c;
+        } else {
+            shape.push(c);
+            prev = c;
+        }
+    }
+    let trimmed_len = shape.trim_end().len();
+    shape.truncate(trimmed_len);
+
+    if config.collapse_in_lists {
+        collapse_in_lists(&shape)
+    } else {
+        shape
+    }
+}
+
+/// Returns the highest number of a `$1` style bind parameter in `sql`, or 0.
+fn max_bind_parameter(sql: &str) -> usize {
+    sql.split('$')
+        .skip(1)
+        .filter_map(|rest| {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest[..end].parse().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Replaces the list after every `IN` which only consists of literals and
+/// placeholders with `(?)`. Subqueries and expressions are left alone.
+fn collapse_in_lists(shape: &str) -> String {
+    let mut collapsed = String::with_capacity(shape.len());
+    let mut rest = shape;
+    while let Some(pos) = find_in_keyword(rest) {
+        let (before, after) = rest.split_at(pos + 2);
+        collapsed.push_str(before);
+        let list = after.trim_start();
+        match list_end(list) {
+            Some(end) => {
+                collapsed.push_str(" (?)");
+                rest = &list[end..];
+            }
+            None => rest = after,
+        }
+    }
+    collapsed.push_str(rest);
+    collapsed
+}
+
+/// Returns the position of the next `IN` keyword outside of quotes.
+fn find_in_keyword(shape: &str) -> Option<usize> {
+    let bytes = shape.as_bytes();
+    let mut quote = None;
+    for (i, &b) in bytes.iter().enumerate() {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'\'' || b == b'"' => quote = Some(b),
+            None => {
+                let is_in = bytes[i..].len() >= 2
+                    && bytes[i..i + 2].eq_ignore_ascii_case(b"in")
+                    && (i == 0 || !is_identifier_byte(bytes[i - 1]))
+                    && !matches!(bytes.get(i + 2), Some(&b) if is_identifier_byte(b));
+                if is_in {
+                    return Some(i);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// If `list` starts with a parenthesized list of literals and placeholders,
+/// returns the position after its closing parenthesis.
+fn list_end(list: &str) -> Option<usize> {
+    let mut chars = list.char_indices();
+    if chars.next()?.1 != '(' {
+        return None;
+    }
+    let mut quoted = false;
+    for (i, c) in chars {
+        match c {
+            '\'' => quoted = !quoted,
+            _ if quoted => {}
+            ')' => return Some(i + 1),
+            '?' | '$' | ',' | '.' | '-' | '+' => {}
+            c if c.is_ascii_digit() || c.is_whitespace() => {}
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Whether an operand rather than an operator follows the normalized `shape`,
+/// e.g. after `=` or `(`, so a sign at this position belongs to a literal.
+fn is_operand_position(shape: &str) -> bool {
+    match shape.trim_end().chars().last() {
+        Some(c) => "(,=<>+-*/%".contains(c),
+        None => true,
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(sql: &str) -> String {
+        normalize_sql(sql, &NormalizationConfig::default())
+    }
+
+    #[test]
+    fn literals_become_placeholders() {
+        assert_eq!(
+            normalize("SELECT * FROM  t\nWHERE a = 5 AND b = 'x' AND c2 = 1.5 -- binds: [1]"),
+            "SELECT * FROM t WHERE a = ? AND b = ? AND c2 = ?"
+        );
+    }
+
+    #[test]
+    fn negative_literals_share_the_shape_of_positive_ones() {
+        assert_eq!(
+            normalize("SELECT 1 FROM t WHERE a = -5"),
+            normalize("SELECT 1 FROM t WHERE a = 5")
+        );
+        assert_eq!(normalize("VALUES (-1, +2)"), "VALUES (?, ?)");
+        assert_eq!(
+            normalize("SELECT a - 5, a-5 FROM t"),
+            "SELECT a - ?, a-? FROM t"
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_literal() {
+        assert_eq!(normalize("SELECT 'it''s', 'a'''"), "SELECT ?, ?");
+        let config = NormalizationConfig {
+            parameterize_literals: false,
+            lowercase_keywords: true,
+            ..NormalizationConfig::default()
+        };
+        assert_eq!(
+            normalize_sql("SELECT 'It''s' FROM \"T\"", &config),
+            "select 'It''s' from \"T\""
+        );
+    }
+
+    #[test]
+    fn numbered_placeholders_continue_after_the_bind_parameters() {
+        assert_eq!(
+            normalize_sql(
+                "SELECT * FROM t WHERE a = $1 AND b = 5 AND c = $2 AND d = 'x'",
+                &NormalizationConfig::pg_stat_statements()
+            ),
+            "SELECT * FROM t WHERE a = $1 AND b = $3 AND c = $2 AND d = $4"
+        );
+    }
+
+    #[test]
+    fn the_pg_stat_statements_preset_matches_its_documented_form() {
+        let pg = |sql| normalize_sql(sql, &NormalizationConfig::pg_stat_statements());
+        assert_eq!(
+            pg("INSERT INTO users (id, name) VALUES (1, 'Ada')"),
+            "INSERT INTO users (id, name) VALUES ($1, $2)"
+        );
+        assert_eq!(
+            pg("/* app */ select * from t where a in (1, 2)"),
+            "/* app */ select * from t where a in ($1, $2)"
+        );
+    }
+
+    #[test]
+    fn in_lists_are_collapsed() {
+        let config = NormalizationConfig {
+            collapse_in_lists: true,
+            ..NormalizationConfig::default()
+        };
+        assert_eq!(
+            normalize_sql(
+                "SELECT 1 FROM t WHERE a IN (1, -2, 3) AND b in ('x', 'y')",
+                &config
+            ),
+            "SELECT ? FROM t WHERE a IN (?) AND b in (?)"
+        );
+        assert_eq!(
+            normalize_sql("SELECT 1 FROM t WHERE a IN ($1, $2, $3)", &config),
+            "SELECT ? FROM t WHERE a IN (?)"
+        );
+        assert_eq!(
+            normalize_sql(
+                "SELECT 1 FROM t WHERE a IN (SELECT b FROM u) AND 'IN (1)' = c",
+                &config
+            ),
+            "SELECT ? FROM t WHERE a IN (SELECT b FROM u) AND ? = c"
+        );
+    }
+
+    #[test]
+    fn literals_are_kept_if_configured() {
+        let config = NormalizationConfig {
+            parameterize_literals: false,
+            ..NormalizationConfig::default()
+        };
+        assert_eq!(
+            normalize_sql("SELECT *  FROM t WHERE a = 5 AND b = 'x'", &config),
+            "SELECT * FROM t WHERE a = 5 AND b = 'x'"
+        );
+    }
+
+    #[test]
+    fn comments_are_only_stripped_if_configured() {
+        let config = NormalizationConfig {
+            strip_comments: true,
+            ..NormalizationConfig::default()
+        };
+        assert_eq!(
+            normalize_sql("SELECT /* hint */ a FROM t -- note", &config),
+            "SELECT a FROM t"
+        );
+        assert_eq!(
+            normalize("SELECT /* hint */ a FROM t"),
+            "SELECT /* hint */ a FROM t"
+        );
+    }
+
+    #[test]
+    fn keywords_are_lowercased_outside_of_quotes() {
+        let config = NormalizationConfig {
+            lowercase_keywords: true,
+            ..NormalizationConfig::default()
+        };
+        assert_eq!(
+            normalize_sql("SELECT \"Name\" FROM Users WHERE id = 1", &config),
+            "select \"Name\" from users where id = ?"
+        );
+    }
+}
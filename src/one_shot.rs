@@ -0,0 +1,41 @@
+use std::cell::Cell;
+
+use crate::DbLogMode;
+
+thread_local! {
+    static NEXT_QUERY_MODE: Cell<Option<DbLogMode>> = const { Cell::new(None) };
+    static NEXT_QUERY_EXPECTS_ROWS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Logs the next query run on the current thread in `mode`, regardless of the
+/// mode of its connection, e.g. `Verbose` for a critical statement.
+/// Later queries use the mode of their connection again.
+///
+/// The override is thread-local: it applies to the next query of whichever
+/// `LoggingConnection` runs on this thread next.
+pub fn log_next_query_as(mode: DbLogMode) {
+    NEXT_QUERY_MODE.with(|next| next.set(Some(mode)));
+}
+
+/// Does not log the next query run on the current thread, like
+/// `log_next_query_as(DbLogMode::NoLog)`.
+pub fn suppress_next_query() {
+    log_next_query_as(DbLogMode::NoLog);
+}
+
+/// Logs a warning if the next query run on the current thread returns or
+/// affects no rows, for reads where an empty result is a bug. Like
+/// `log_next_query_as`, this only applies to the next query of this thread.
+pub fn expect_rows_from_next_query() {
+    NEXT_QUERY_EXPECTS_ROWS.with(|next| next.set(true));
+}
+
+/// Takes the override for the query about to run, if any.
+pub(crate) fn take() -> Option<DbLogMode> {
+    NEXT_QUERY_MODE.with(Cell::take)
+}
+
+/// Takes whether the query about to run is expected to return rows.
+pub(crate) fn take_expects_rows() -> bool {
+    NEXT_QUERY_EXPECTS_ROWS.with(Cell::take)
+}
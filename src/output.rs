@@ -0,0 +1,221 @@
+use std::fmt;
+use std::io::Write;
+use std::sync::Mutex;
+
+use std::time::Duration;
+
+use crate::kind::LOG_TARGETS;
+use crate::DbLogMode;
+
+/// The target of lines which are not about a single query.
+#[cfg(not(feature = "tracing-events"))]
+const OTHER_TARGET: &str = LOG_TARGETS[3];
+
+/// Structured data about the query of a log line.
+/// With the `kv` feature it is attached to `log` records as key-values,
+/// with the `tracing-events` feature to `tracing` events as fields.
+pub(crate) struct LineFields<'a> {
+    pub(crate) query: &'a str,
+    pub(crate) kind: crate::QueryKind,
+    pub(crate) duration: Duration,
+    /// The time spent waiting for a pooled connection, if it was recorded.
+    pub(crate) wait: Option<Duration>,
+    pub(crate) rows: Option<usize>,
+    pub(crate) slow: bool,
+    /// Whether the query is hand-written SQL.
+    pub(crate) raw: bool,
+    /// The number of statements of a batch.
+    pub(crate) statements: Option<usize>,
+    /// The correlation ID of the thread which ran the query.
+    pub(crate) correlation_id: Option<&'a str>,
+}
+
+impl LineFields<'_> {
+    /// The number of rows an insert or `COPY` affected, which tells batched
+    /// inserts apart from one-row-at-a-time loops.
+    pub(crate) fn batch_rows(&self) -> Option<usize> {
+        self.rows
+            .filter(|_| matches!(self.kind, crate::QueryKind::Insert | crate::QueryKind::Copy))
+    }
+}
+
+/// Where the log lines of a `LoggingConnection` end up.
+#[derive(Default)]
+pub enum LogOutput {
+    /// Use the `log` crate, or `tracing` with the `tracing-events` feature.
+    /// The `Excessive` modes print to stdout instead, so they work even if no
+    /// logger is configured.
+    ///
+    /// Lines about queries are logged under a target per kind of query, so
+    /// they can be filtered like `RUST_LOG=diesel_logger=warn,diesel_logger::write=debug`:
+    /// `diesel_logger::select`, `diesel_logger::write` for inserts, updates,
+    /// deletes and `COPY`, `diesel_logger::ddl` and `diesel_logger::other`.
+    /// Lines which are not about a single query, e.g. failed connection
+    /// attempts, are logged under `diesel_logger::other` as well.
+    /// With `tracing-events`, filter on the `kind` field instead.
+    #[default]
+    Log,
+    /// Write every line, prefixed with its timestamp and level, to a writer.
+    /// The timestamp is left out if the connection does not show timestamps.
+    Writer(Mutex<Box<dyn Write + Send>>),
+    /// Send every line to syslog, with a priority derived from its level.
+    #[cfg(feature = "syslog")]
+    Syslog(Mutex<syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>>),
+}
+
+impl LogOutput {
+    /// Writes every line to `writer`, which can be any `Write`, e.g. a file,
+    /// a buffer or an in-memory target in tests.
+    ///
+    /// Writing happens on the thread which ran the query, under a lock. To
+    /// never block queries on I/O, pass a non-blocking writer like the one of
+    /// `tracing_appender::non_blocking(std::io::stdout())`, and keep its
+    /// `WorkerGuard` alive for as long as lines should be flushed.
+    pub fn writer<W: Write + Send + 'static>(writer: W) -> Self {
+        LogOutput::Writer(Mutex::new(Box::new(writer)))
+    }
+
+    /// Connects to the local syslog daemon, logging with the `user` facility
+    /// under the given process name.
+    #[cfg(feature = "syslog")]
+    pub fn syslog(process: &str) -> syslog::Result<Self> {
+        let formatter = syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_USER,
+            hostname: None,
+            process: process.to_owned(),
+            pid: std::process::id(),
+        };
+        Ok(LogOutput::Syslog(Mutex::new(syslog::unix(formatter)?)))
+    }
+
+    /// Returns false if a line with this level would be discarded anyway,
+    /// so formatting it can be skipped.
+    pub(crate) fn enabled(&self, level: log::Level, mode: DbLogMode) -> bool {
+        match self {
+            LogOutput::Log => {
+                is_excessive(mode)
+                    || LOG_TARGETS
+                        .iter()
+                        .any(|target| log_enabled!(target: target, level))
+            }
+            _ => true,
+        }
+    }
+
+    /// Emits a line, prefixed with `timestamp` where the output shows one.
+    pub(crate) fn emit(
+        &self,
+        level: log::Level,
+        mode: DbLogMode,
+        timestamp: Option<crate::clock::Timestamp>,
+        message: &str,
+        fields: Option<&LineFields<'_>>,
+    ) {
+        match self {
+            LogOutput::Log if is_excessive(mode) => match timestamp {
+                Some(timestamp) => println!("[{}]: {}", timestamp, message),
+                None => println!("{}", message),
+            },
+            #[cfg(feature = "tracing-events")]
+            LogOutput::Log => match fields {
+                Some(fields) => tracing_event!(
+                    level,
+                    duration_ms = fields.duration.as_secs_f64() * 1000.0,
+                    wait_ms = fields.wait.map(|wait| wait.as_secs_f64() * 1000.0),
+                    rows = fields.rows.map(|rows| rows as u64),
+                    batch_rows = fields.batch_rows().map(|rows| rows as u64),
+                    statements = fields.statements.map(|statements| statements as u64),
+                    kind = fields.kind.as_str(),
+                    slow = fields.slow,
+                    raw = fields.raw,
+                    req = fields.correlation_id,
+                    "{}",
+                    message
+                ),
+                None => tracing_event!(level, "{}", message),
+            },
+            #[cfg(all(feature = "kv", not(feature = "tracing-events")))]
+            LogOutput::Log => match fields {
+                Some(fields) => log::log!(
+                    target: fields.kind.log_target(),
+                    level,
+                    duration_ms = fields.duration.as_secs_f64() * 1000.0,
+                    wait_ms = fields.wait.map(|wait| wait.as_secs_f64() * 1000.0),
+                    rows = fields.rows,
+                    batch_rows = fields.batch_rows(),
+                    statements = fields.statements,
+                    kind = fields.kind.as_str(),
+                    slow = fields.slow,
+                    raw = fields.raw,
+                    req = fields.correlation_id;
+                    "{}",
+                    message
+                ),
+                None => log::log!(target: OTHER_TARGET, level, "{}", message),
+            },
+            #[cfg(not(any(feature = "kv", feature = "tracing-events")))]
+            LogOutput::Log => match fields {
+                Some(fields) => log::log!(target: fields.kind.log_target(), level, "{}", message),
+                None => log::log!(target: OTHER_TARGET, level, "{}", message),
+            },
+            LogOutput::Writer(writer) => {
+                let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                // Failing to write a log line must not fail the query.
+                let _ = match timestamp {
+                    Some(timestamp) => writeln!(writer, "[{}] {}: {}", timestamp, level, message),
+                    None => writeln!(writer, "{}: {}", level, message),
+                };
+            }
+            #[cfg(feature = "syslog")]
+            LogOutput::Syslog(logger) => {
+                let mut logger = logger.lock().unwrap_or_else(|e| e.into_inner());
+                let _ = match level {
+                    log::Level::Error => logger.err(message),
+                    log::Level::Warn => logger.warning(message),
+                    log::Level::Info => logger.info(message),
+                    log::Level::Debug | log::Level::Trace => logger.debug(message),
+                };
+            }
+        }
+    }
+}
+
+impl fmt::Debug for LogOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogOutput::Log => f.write_str("Log"),
+            LogOutput::Writer(_) => f.write_str("Writer"),
+            #[cfg(feature = "syslog")]
+            LogOutput::Syslog(_) => f.write_str("Syslog"),
+        }
+    }
+}
+
+fn is_excessive(mode: DbLogMode) -> bool {
+    mode == DbLogMode::Excessive || mode == DbLogMode::ExcessiveMini
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QueryKind;
+
+    #[test]
+    fn only_inserts_and_copies_have_batch_rows() {
+        let fields = |kind| LineFields {
+            query: "",
+            kind,
+            duration: Duration::default(),
+            wait: None,
+            rows: Some(3),
+            slow: false,
+            raw: false,
+            statements: None,
+            correlation_id: None,
+        };
+        assert_eq!(fields(QueryKind::Insert).batch_rows(), Some(3));
+        assert_eq!(fields(QueryKind::Copy).batch_rows(), Some(3));
+        assert_eq!(fields(QueryKind::Update).batch_rows(), None);
+        assert_eq!(fields(QueryKind::Select).batch_rows(), None);
+    }
+}
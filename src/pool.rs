@@ -0,0 +1,100 @@
+use diesel::r2d2::ManageConnection;
+use diesel::Connection;
+
+use crate::{global_log_mode, DbLogMode, LoggingConfig, LoggingConnection};
+
+/// Wraps every connection another r2d2 manager creates in a `LoggingConnection`,
+/// e.g. `Pool::new(LoggingConnectionManager::new(ConnectionManager::<PgConnection>::new(url)))`,
+/// so an existing pool setup gains logging without a custom manager.
+///
+/// Connections start in the global log mode and with the global defaults,
+/// unless a mode or configuration is set here. Validity checks and broken
+/// connections are left to the wrapped manager, on the wrapped connections,
+/// so health checks of the pool are not logged.
+#[derive(Debug)]
+pub struct LoggingConnectionManager<M> {
+    manager: M,
+    log_mode: Option<DbLogMode>,
+    config: Option<LoggingConfig>,
+}
+
+impl<M> LoggingConnectionManager<M> {
+    pub fn new(manager: M) -> Self {
+        LoggingConnectionManager {
+            manager,
+            log_mode: None,
+            config: None,
+        }
+    }
+
+    /// Creates connections in `log_mode` instead of the global log mode.
+    pub fn with_log_mode(mut self, log_mode: DbLogMode) -> Self {
+        self.log_mode = Some(log_mode);
+        self
+    }
+
+    /// Creates connections with `config` instead of the global defaults,
+    /// e.g. the `config()` of a connection configured before.
+    pub fn with_config(mut self, config: LoggingConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// The wrapped manager.
+    pub fn inner(&self) -> &M {
+        &self.manager
+    }
+}
+
+impl<M> ManageConnection for LoggingConnectionManager<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    type Connection = LoggingConnection<M::Connection>;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let log_mode = self.log_mode.unwrap_or_else(global_log_mode);
+        let mut conn = LoggingConnection::new(self.manager.connect()?, log_mode);
+        if let Some(config) = &self.config {
+            conn.set_config(config.clone());
+        }
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.is_valid(&mut conn.conn)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.manager.has_broken(&mut conn.conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::r2d2::ConnectionManager;
+    use diesel::sqlite::{Sqlite, SqliteConnection};
+
+    use super::*;
+
+    #[test]
+    fn connections_get_the_mode_and_configuration_of_the_manager() {
+        let config = LoggingConfig {
+            show_backend: true,
+            ..LoggingConfig::default()
+        };
+        let manager =
+            LoggingConnectionManager::new(ConnectionManager::<SqliteConnection>::new(":memory:"))
+                .with_log_mode(DbLogMode::Verbose)
+                .with_config(config);
+        let conn = manager.connect().unwrap();
+        assert_eq!(conn.log_mode, DbLogMode::Verbose);
+        assert!(conn.config.show_backend);
+        assert_eq!(
+            conn.config.backend,
+            crate::backend::backend_name::<Sqlite>()
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use diesel::connection::{SimpleConnection, TransactionManager};
+use diesel::deserialize::{self, QueryableByName};
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::row::NamedRow;
+use diesel::sql_types::Text;
+use std::time::Duration;
+
+use crate::LoggingConnection;
+
+impl LoggingConnection<PgConnection> {
+    /// Runs `EXPLAIN (ANALYZE true, BUFFERS true)` for read-only queries above
+    /// the very slow threshold and logs the plan with its buffer statistics at `warn`.
+    ///
+    /// `ANALYZE` executes the query a second time, so every very slow query
+    /// runs twice and takes about twice as long for the caller. This is strictly
+    /// opt-in. It is only done for plain `SELECT` statements whose SQL is
+    /// runnable as logged, i.e. queries without bind parameters. Statements
+    /// which may modify data are never explained, but functions with side
+    /// effects called from a `SELECT` cannot be detected.
+    ///
+    /// Inside a transaction the explain runs in a savepoint which is rolled back
+    /// afterwards, so a failing explain does not abort the transaction.
+    pub fn set_deep_explain(&mut self, enabled: bool) {
+        self.explain = if enabled { Some(explain_analyze) } else { None };
+    }
+
+    /// Logs an extra line at `debug`, tagged `prepare`, for the first execution
+    /// of every statement on this connection, e.g.
+    /// `[prepare] Prepared and ran in 4.210 ms: SELECT ...`, to tell the cost of
+    /// cold statements apart from cached ones.
+    ///
+    /// Diesel does not expose the prepare step, so this is a heuristic: it
+    /// caches the prepared statements of queries with a static `QueryId`, so the
+    /// first execution of such a query prepared it, and its time includes both
+    /// preparing and running it. Queries without a static `QueryId`, e.g.
+    /// `sql_query`, are prepared every time and never tagged.
+    pub fn set_log_prepares(&mut self, enabled: bool) {
+        self.prepared_statements.set_enabled(enabled);
+    }
+
+    /// Runs a plain `EXPLAIN` for read-only queries which took at least
+    /// `min_duration` and prefixes their line with the estimated total cost of
+    /// the plan, e.g. `[cost=1234.50]`, if it is at least `min_cost`. This
+    /// catches expensive plans which happen to run fast on a warm cache.
+    ///
+    /// Unlike `set_deep_explain` it does not run the query again, but it still
+    /// takes a round trip. It is restricted to the same queries: plain `SELECT`
+    /// statements without bind parameters. Like it, it runs in a savepoint
+    /// inside a transaction, so a failing `EXPLAIN` does not abort it.
+    ///
+    /// Pass `None` to not estimate costs again, which is the default.
+    pub fn set_cost_estimates(&mut self, thresholds: Option<(Duration, f64)>) {
+        self.config.cost_estimates = thresholds;
+        self.estimate_cost = thresholds.map(|_| estimate_cost as fn(&PgConnection, &str) -> _);
+    }
+}
+
+fn estimate_cost(conn: &PgConnection, query: &str) -> Option<f64> {
+    let query = runnable_sql(query).filter(|query| is_read_only(query))?;
+    let explain = diesel::sql_query(format!("EXPLAIN {}", query));
+    let plan = isolated(conn, || conn.query_by_name::<_, PlanLine>(&explain)).ok()?;
+    total_cost(&plan.first()?.0)
+}
+
+/// Parses the total cost from the top line of a plan,
+/// e.g. `35.50` from `Seq Scan on t  (cost=0.00..35.50 rows=2550 width=4)`.
+fn total_cost(line: &str) -> Option<f64> {
+    let costs = &line[line.find("(cost=")?..];
+    let total = &costs[costs.find("..")? + 2..];
+    total[..total.find(' ')?].parse().ok()
+}
+
+fn explain_analyze(conn: &PgConnection, query: &str) -> Option<String> {
+    let query = runnable_sql(query).filter(|query| is_read_only(query))?;
+    let explain = diesel::sql_query(format!("EXPLAIN (ANALYZE true, BUFFERS true) {}", query));
+    Some(
+        match isolated(conn, || conn.query_by_name::<_, PlanLine>(&explain)) {
+            Ok(plan) => {
+                let plan = plan.into_iter().map(|l| l.0).collect::<Vec<_>>();
+                format!("Plan of slow query {}:\n{}", query, plan.join("\n"))
+            }
+            Err(e) => format!("Failed to explain slow query {}: {}", query, e),
+        },
+    )
+}
+
+/// Runs a statement of this crate in a savepoint if the caller has a
+/// transaction open, and rolls back to it afterwards, so a failing statement
+/// does not abort the transaction and whatever it did is undone.
+fn isolated<T>(conn: &PgConnection, run: impl FnOnce() -> QueryResult<T>) -> QueryResult<T> {
+    if TransactionManager::<PgConnection>::get_transaction_depth(conn.transaction_manager()) == 0 {
+        return run();
+    }
+    conn.batch_execute("SAVEPOINT diesel_logger")?;
+    let result = run();
+    conn.batch_execute("ROLLBACK TO SAVEPOINT diesel_logger; RELEASE SAVEPOINT diesel_logger")?;
+    result
+}
+
+/// Returns the SQL of a logged query if it can be run as is,
+/// which is not the case if `debug_query` rendered bind parameters.
+fn runnable_sql(query: &str) -> Option<&str> {
+    match query.find(" -- binds: ") {
+        Some(pos) if query[pos..].trim_end() == " -- binds: []" => Some(&query[..pos]),
+        Some(_) => None,
+        None => Some(query),
+    }
+}
+
+/// Returns true for a single `SELECT` which contains no data modifying keyword,
+/// not even inside a CTE or string literal.
+fn is_read_only(query: &str) -> bool {
+    if crate::QueryKind::classify(query) != crate::QueryKind::Select {
+        return false;
+    }
+    const MODIFYING: &[&str] = &[
+        "INSERT", "UPDATE", "DELETE", "MERGE", "TRUNCATE", "CREATE", "ALTER", "DROP", "GRANT",
+        "REVOKE", "COPY", "CALL", "DO", "INTO",
+    ];
+    !query.contains(';')
+        && !query
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .any(|word| MODIFYING.iter().any(|k| word.eq_ignore_ascii_case(k)))
+}
+
+struct PlanLine(String);
+
+impl QueryableByName<Pg> for PlanLine {
+    fn build<R: NamedRow<Pg>>(row: &R) -> deserialize::Result<Self> {
+        // libpq folds unquoted column names to lower case.
+        row.get::<Text, String>("\"QUERY PLAN\"").map(PlanLine)
+    }
+}
@@ -0,0 +1,10 @@
+//! The types and functions needed to set up logging, for
+//! `use diesel_logger::prelude::*;`.
+
+pub use crate::{
+    clear_correlation_id, log_next_query_as, set_correlation_id, set_global_defaults,
+    set_global_log_mode, set_max_logs_per_sec, suppress_next_query, CommitCallback, DbLogMode,
+    ErrorFormatter, LogFormat, LogLevels, LogOutput, LogPredicate, LoggingConfig,
+    LoggingConnection, QueryEvent, QueryKind, QuerySink, SlowQueryCallback, SlowQueryClassifier,
+    SlowQueryRateLimit, SlowQueryThresholds,
+};
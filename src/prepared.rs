@@ -0,0 +1,73 @@
+use std::any::TypeId;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Guesses which executions prepared a statement. Diesel does not expose the
+/// prepare step, but it caches the prepared statement of every query with a
+/// static `QueryId` per connection, so the first execution of such a query on
+/// a connection is the one which prepared it.
+#[derive(Default)]
+pub(crate) struct PreparedStatements {
+    enabled: bool,
+    seen: Mutex<HashSet<TypeId>>,
+}
+
+impl PreparedStatements {
+    #[cfg(feature = "postgres")]
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.seen
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+        }
+    }
+
+    /// Whether a query with this id is run for the first time on the connection.
+    pub(crate) fn is_first_execution(&self, static_id: Option<TypeId>) -> bool {
+        match static_id {
+            Some(id) if self.enabled => self
+                .seen
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(id),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_first_execution_of_a_static_query_prepares_it() {
+        let prepared = PreparedStatements {
+            enabled: true,
+            ..PreparedStatements::default()
+        };
+        let id = Some(TypeId::of::<u8>());
+        assert!(prepared.is_first_execution(id));
+        assert!(!prepared.is_first_execution(id));
+        assert!(prepared.is_first_execution(Some(TypeId::of::<u16>())));
+        assert!(!prepared.is_first_execution(None));
+    }
+
+    #[test]
+    fn nothing_is_prepared_when_disabled() {
+        let prepared = PreparedStatements::default();
+        assert!(!prepared.is_first_execution(Some(TypeId::of::<u8>())));
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn disabling_forgets_the_prepared_statements() {
+        let mut prepared = PreparedStatements::default();
+        prepared.set_enabled(true);
+        assert!(prepared.is_first_execution(Some(TypeId::of::<u8>())));
+        prepared.set_enabled(false);
+        prepared.set_enabled(true);
+        assert!(prepared.is_first_execution(Some(TypeId::of::<u8>())));
+    }
+}
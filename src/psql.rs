@@ -0,0 +1,177 @@
+use crate::binds;
+
+/// Turns the output of `debug_query` into a statement which can be pasted into
+/// `psql`: the bind values are inlined as literals, whitespace is collapsed
+/// onto a single line and a `;` is appended. `--` comments are dropped, as
+/// they would comment out the rest of the line.
+///
+/// Bind values replace `$1` style placeholders, or `?` in queries without
+/// them, as of SQLite and MySQL. A query with `$1` style placeholders is one
+/// for Postgres, where `?` is an operator of `jsonb`.
+///
+/// Bind values are recovered from their `Debug` output, which works for
+/// numbers, booleans, strings and `None`. Anything else is inlined as a
+/// string literal of its `Debug` output.
+pub(crate) fn psql_ready(query: &str) -> String {
+    let (sql, binds) = match query.find(" -- binds: ") {
+        Some(pos) => (
+            &query[..pos],
+            binds::split_binds(&query[pos + " -- binds: ".len()..]),
+        ),
+        None => (query, Vec::new()),
+    };
+
+    let question_marks = !has_numbered_placeholder(sql);
+    let mut statement = String::with_capacity(query.len() + 1);
+    let mut chars = sql.chars().peekable();
+    let mut next_bind = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                statement.push(c);
+                for inner in chars.by_ref() {
+                    statement.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                statement.push(c);
+                statement.extend(chars.next());
+                let mut prev = ' ';
+                for inner in chars.by_ref() {
+                    statement.push(inner);
+                    if prev == '*' && inner == '/' {
+                        break;
+                    }
+                    prev = inner;
+                }
+            }
+            '$' if matches!(chars.peek(), Some(c) if c.is_ascii_digit()) => {
+                let mut index = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    index = index * 10 + digit as usize;
+                    chars.next();
+                }
+                match index.checked_sub(1).and_then(|i| binds.get(i)) {
+                    Some(bind) => statement.push_str(&sql_literal(bind)),
+                    None => statement.push_str(&format!("${}", index)),
+                }
+            }
+            '?' if question_marks && next_bind < binds.len() => {
+                statement.push_str(&sql_literal(binds[next_bind]));
+                next_bind += 1;
+            }
+            c if c.is_whitespace() => {
+                if !statement.ends_with(' ') {
+                    statement.push(' ');
+                }
+            }
+            c => statement.push(c),
+        }
+    }
+    let trimmed_len = statement.trim_end().trim_end_matches(';').len();
+    statement.truncate(trimmed_len);
+    statement.push(';');
+    statement
+}
+
+/// Whether `sql` has a `$1` style placeholder outside of quotes.
+fn has_numbered_placeholder(sql: &str) -> bool {
+    let mut quote = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '$' && matches!(chars.peek(), Some(c) if c.is_ascii_digit()) => {
+                return true;
+            }
+            None => {}
+        }
+    }
+    false
+}
+
+/// Converts the `Debug` output of a bind value into a SQL literal.
+fn sql_literal(bind: &str) -> String {
+    if let Some(inner) = bind.strip_prefix("Some(").and_then(|b| b.strip_suffix(')')) {
+        return sql_literal(inner);
+    }
+    if bind == "None" {
+        return "NULL".to_owned();
+    }
+    let is_number =
+        bind.starts_with(|c: char| c.is_ascii_digit() || c == '-') && bind.parse::<f64>().is_ok();
+    if bind == "true" || bind == "false" || is_number {
+        return bind.to_owned();
+    }
+    let text = match bind.strip_prefix('"').and_then(|b| b.strip_suffix('"')) {
+        Some(inner) => unescape_debug(inner),
+        None => bind.to_owned(),
+    };
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Undoes the escaping of `str`'s `Debug` output for the common escapes.
+fn unescape_debug(escaped: &str) -> String {
+    let mut text = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some('t') => text.push('\t'),
+            Some('0') => text.push('\0'),
+            Some(other) => text.push(other),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_are_inlined_as_literals() {
+        assert_eq!(
+            psql_ready(
+                "SELECT * FROM t WHERE a = $1 AND b = $2 AND c = $1 -- binds: [5, \"it's\"]"
+            ),
+            "SELECT * FROM t WHERE a = 5 AND b = 'it''s' AND c = 5;"
+        );
+        assert_eq!(
+            psql_ready("INSERT INTO t VALUES (?, ?, ?) -- binds: [Some(true), None, -1.5]"),
+            "INSERT INTO t VALUES (true, NULL, -1.5);"
+        );
+    }
+
+    #[test]
+    fn line_comments_do_not_swallow_the_rest_of_the_statement() {
+        assert_eq!(
+            psql_ready("SELECT 1 -- note\nFROM t\nWHERE a = '--' /* x */"),
+            "SELECT 1 FROM t WHERE a = '--' /* x */;"
+        );
+    }
+
+    #[test]
+    fn question_marks_stay_operators_next_to_numbered_placeholders() {
+        assert_eq!(
+            psql_ready("SELECT * FROM t WHERE data ? 'key' AND id = $1 -- binds: [3]"),
+            "SELECT * FROM t WHERE data ? 'key' AND id = 3;"
+        );
+        assert_eq!(
+            psql_ready("SELECT '$1', ? -- binds: [3]"),
+            "SELECT '$1', 3;"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::QueryEvent;
+
+/// A destination for the `QueryEvent` of every query, registered with
+/// `LoggingConnection::add_sink`. Every sink of a connection receives every
+/// event, independently of the log mode, e.g. a `LogSink` for humans next to
+/// a `CallbackSink` feeding metrics and a `FileSink` for auditing.
+///
+/// Sinks run on the thread which ran the query, before the query returns,
+/// so they should be quick.
+pub trait QuerySink: Send + Sync {
+    /// Called with the event of every query, failed ones included.
+    fn on_query(&self, event: &QueryEvent);
+}
+
+/// Logs every event as one line with the `log` crate, at `info` for slow
+/// queries and at `debug` otherwise.
+#[derive(Debug, Default)]
+pub struct LogSink;
+
+impl QuerySink for LogSink {
+    fn on_query(&self, event: &QueryEvent) {
+        let level = if event.slow {
+            log::Level::Info
+        } else {
+            log::Level::Debug
+        };
+        emit_log!(level, "{}", line(event));
+    }
+}
+
+/// Appends every event as one line to a file, e.g. for an audit trail.
+#[derive(Debug)]
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    /// Opens `path` for appending, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl QuerySink for FileSink {
+    fn on_query(&self, event: &QueryEvent) {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        // Failing to write an event must not fail the query.
+        let _ = writeln!(
+            file,
+            "[{}] {}",
+            crate::clock::Timestamp(event.start_time),
+            line(event)
+        );
+    }
+}
+
+/// Calls a closure with every event.
+pub struct CallbackSink<F>(pub F);
+
+impl<F> QuerySink for CallbackSink<F>
+where
+    F: Fn(&QueryEvent) + Send + Sync,
+{
+    fn on_query(&self, event: &QueryEvent) {
+        (self.0)(event)
+    }
+}
+
+/// The line of an event, e.g. `#3 select 1.250 ms rows=1: SELECT ...`.
+fn line(event: &QueryEvent) -> String {
+    let mut line = format!(
+        "#{} {} {:.3} ms",
+        event.seq,
+        event.kind.as_str(),
+        event.duration.as_secs_f64() * 1000.0
+    );
+    if let Some(rows) = event.rows {
+        line.push_str(&format!(" rows={}", rows));
+    }
+    line.push_str(": ");
+    line.push_str(&event.query);
+    line
+}
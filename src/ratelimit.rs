@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::fingerprint::fingerprint;
+
+/// At most this many query shapes are rate limited per connection,
+/// slow queries of further shapes are always logged.
+const MAX_SHAPES: usize = 10_000;
+
+/// Configuration for collapsing repeated slow query lines of the same query shape.
+///
+/// At most `max_per_window` slow executions of a shape are logged per `window`.
+/// Further ones are counted and summarized in a single line once the window is
+/// over and the shape is slow again, or by `LoggingConnection::report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowQueryRateLimit {
+    /// How long a window lasts, starting with the first slow execution in it.
+    pub window: Duration,
+    /// How many slow executions of a query shape are logged per window.
+    pub max_per_window: u32,
+}
+
+impl Default for SlowQueryRateLimit {
+    fn default() -> Self {
+        SlowQueryRateLimit {
+            window: Duration::from_secs(60),
+            max_per_window: 10,
+        }
+    }
+}
+
+/// Slow executions of one query shape in the current window.
+struct Window {
+    start: Instant,
+    shape: String,
+    logged: u32,
+    suppressed: u32,
+}
+
+/// The suppressed executions of a query shape in a window which is over.
+pub(crate) struct Suppressed {
+    pub(crate) shape: String,
+    pub(crate) count: u32,
+    pub(crate) window: Duration,
+}
+
+impl Suppressed {
+    pub(crate) fn message(&self) -> String {
+        format!(
+            "Same slow query seen {} more times in the last {}: {}",
+            self.count,
+            crate::format_duration_auto(self.window, None),
+            self.shape
+        )
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SlowQueryLimiter {
+    config: Option<SlowQueryRateLimit>,
+    windows: Mutex<HashMap<u64, Window>>,
+}
+
+impl SlowQueryLimiter {
+    pub(crate) fn set_config(&mut self, config: Option<SlowQueryRateLimit>) {
+        self.config = config;
+        self.windows().clear();
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// Counts a slow execution of a query shape. Returns whether it may be logged,
+    /// and the summary of the previous window of the shape, if that has to be logged.
+    pub(crate) fn check(&self, shape: &str) -> (bool, Option<Suppressed>) {
+        let config = match self.config {
+            Some(config) => config,
+            None => return (true, None),
+        };
+        let key = fingerprint(shape);
+        let now = Instant::now();
+
+        let mut windows = self.windows();
+        if windows.len() >= MAX_SHAPES && !windows.contains_key(&key) {
+            return (true, None);
+        }
+        let window = windows.entry(key).or_insert_with(|| Window {
+            start: now,
+            shape: shape.to_owned(),
+            logged: 0,
+            suppressed: 0,
+        });
+
+        let mut previous = None;
+        let elapsed = now.duration_since(window.start);
+        if elapsed >= config.window {
+            if window.suppressed > 0 {
+                previous = Some(Suppressed {
+                    shape: window.shape.clone(),
+                    count: window.suppressed,
+                    window: elapsed,
+                });
+            }
+            window.start = now;
+            window.logged = 0;
+            window.suppressed = 0;
+        }
+
+        if window.logged < config.max_per_window {
+            window.logged += 1;
+            (true, previous)
+        } else {
+            window.suppressed += 1;
+            (false, previous)
+        }
+    }
+
+    /// Takes the summaries of all windows with suppressed executions.
+    pub(crate) fn drain(&self) -> Vec<Suppressed> {
+        let now = Instant::now();
+        self.windows()
+            .drain()
+            .filter(|(_, window)| window.suppressed > 0)
+            .map(|(_, window)| Suppressed {
+                window: now.duration_since(window.start),
+                shape: window.shape,
+                count: window.suppressed,
+            })
+            .collect()
+    }
+
+    fn windows(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Window>> {
+        self.windows.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(window: Duration, max_per_window: u32) -> SlowQueryLimiter {
+        let mut limiter = SlowQueryLimiter::default();
+        limiter.set_config(Some(SlowQueryRateLimit {
+            window,
+            max_per_window,
+        }));
+        limiter
+    }
+
+    #[test]
+    fn shapes_are_limited_separately() {
+        let limiter = limited(Duration::from_secs(60), 2);
+        let logged = |shape| limiter.check(shape).0;
+        assert!(logged("SELECT a"));
+        assert!(logged("SELECT a"));
+        assert!(!logged("SELECT a"));
+        assert!(logged("SELECT b"));
+        assert!(!logged("SELECT a"));
+
+        let suppressed = limiter.drain();
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0].shape, "SELECT a");
+        assert_eq!(suppressed[0].count, 2);
+        assert!(limiter.drain().is_empty());
+    }
+
+    #[test]
+    fn the_next_window_reports_the_suppressed_executions() {
+        let limiter = limited(Duration::from_secs(0), 1);
+        let (logged, previous) = limiter.check("SELECT a");
+        assert!(logged && previous.is_none());
+        // Every check starts a new window, as the window lasts no time.
+        assert!(limiter.check("SELECT a").0);
+
+        let limiter = limited(Duration::from_millis(20), 1);
+        limiter.check("SELECT a");
+        assert!(!limiter.check("SELECT a").0);
+        std::thread::sleep(Duration::from_millis(30));
+        let (logged, previous) = limiter.check("SELECT a");
+        assert!(logged);
+        let previous = previous.unwrap();
+        assert_eq!(previous.count, 1);
+        assert!(previous
+            .message()
+            .starts_with("Same slow query seen 1 more times in the last "));
+    }
+
+    #[test]
+    fn everything_is_logged_without_a_limit() {
+        let limiter = SlowQueryLimiter::default();
+        assert!(!limiter.is_enabled());
+        assert!((0..100).all(|_| limiter.check("SELECT a").0));
+    }
+}
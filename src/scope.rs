@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Tracks whether a connection runs a closure in a scope, e.g. the one of
+/// `LoggingConnection::run_as_migrations`. Scopes can be nested.
+#[derive(Default)]
+pub(crate) struct Scope {
+    active: AtomicBool,
+}
+
+impl Scope {
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Runs `f` inside the scope, leaving it again even if `f` panics.
+    pub(crate) fn run<R>(&self, f: impl FnOnce() -> R) -> R {
+        let was_active = self.active.swap(true, Ordering::Relaxed);
+        let _guard = LeaveOnDrop {
+            scope: self,
+            was_active,
+        };
+        f()
+    }
+}
+
+struct LeaveOnDrop<'a> {
+    scope: &'a Scope,
+    was_active: bool,
+}
+
+impl Drop for LeaveOnDrop<'_> {
+    fn drop(&mut self) {
+        self.scope.active.store(self.was_active, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_scopes_leave_the_outer_one_active() {
+        let scope = Scope::default();
+        scope.run(|| {
+            scope.run(|| assert!(scope.is_active()));
+            assert!(scope.is_active());
+        });
+        assert!(!scope.is_active());
+    }
+
+    #[test]
+    fn scopes_are_left_on_panic() {
+        let scope = Scope::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scope.run(|| panic!("boom"))
+        }));
+        assert!(result.is_err());
+        assert!(!scope.is_active());
+    }
+}
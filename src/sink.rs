@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use diesel::Connection;
+
+use crate::{LogOutput, LoggingConnection, QueryEvent, TimeSource};
+
+/// Collects the `QueryEvent`s of a connection, to assert on them in tests,
+/// e.g. `assert!(sink.events()[0].slow)` after running a slow query.
+#[derive(Debug)]
+pub struct CaptureSink {
+    receiver: Mutex<Receiver<QueryEvent>>,
+    events: Mutex<Vec<QueryEvent>>,
+}
+
+impl CaptureSink {
+    /// Opens the event channel of `conn` and collects everything sent to it.
+    pub fn attach<C: Connection>(conn: LoggingConnection<C>) -> (LoggingConnection<C>, Self) {
+        let (conn, receiver) = conn.with_event_channel();
+        let sink = CaptureSink {
+            receiver: Mutex::new(receiver),
+            events: Mutex::new(Vec::new()),
+        };
+        (conn, sink)
+    }
+
+    /// Every event captured so far, oldest first.
+    pub fn events(&self) -> Vec<QueryEvent> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = self.receiver.lock().unwrap_or_else(|e| e.into_inner());
+        events.extend(receiver.try_iter());
+        events.clone()
+    }
+
+    /// Forgets the events captured so far.
+    pub fn clear(&self) {
+        self.events();
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// A clock which always returns the same time, for deterministic timestamps,
+/// e.g. `conn.set_time_source(Arc::new(FixedClock(UNIX_EPOCH)))`.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub SystemTime);
+
+impl TimeSource for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// Collects the lines written to an output, to compare them with snapshots of
+/// the expected output, e.g.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use std::time::{Duration, UNIX_EPOCH};
+/// # use diesel::sqlite::SqliteConnection;
+/// # use diesel::Connection;
+/// # use diesel_logger::{CapturedLines, DbLogMode, FixedClock, LoggingConnection};
+/// # let conn = SqliteConnection::establish(":memory:").unwrap();
+/// # let mut conn = LoggingConnection::new(conn, DbLogMode::Standard);
+/// let lines = CapturedLines::default();
+/// conn.set_output(lines.output());
+/// conn.set_time_source(Arc::new(FixedClock(UNIX_EPOCH)));
+/// conn.set_duration_bucket(Some(Duration::from_secs(3600)));
+/// conn.execute("SELECT 1").unwrap();
+/// assert_eq!(
+///     lines.lines(),
+///     ["[1970-01-01 00:00:00 UTC] DEBUG: [SELECT] Query ran in 0 µs: SELECT 1"]
+/// );
+/// ```
+///
+/// Durations are measured, not taken from the clock, so round them with a
+/// large `set_duration_bucket` to make them deterministic as well. They are
+/// rounded down to `0 µs` then, not shown as `<1 µs` like a query which ran
+/// for less than a microsecond.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedLines {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl CapturedLines {
+    /// An output which writes to these lines.
+    pub fn output(&self) -> Arc<LogOutput> {
+        Arc::new(LogOutput::writer(self.clone()))
+    }
+
+    /// Every line written so far, oldest first. Lines of queries which span
+    /// several lines, e.g. `Excessive` ones, are split.
+    pub fn lines(&self) -> Vec<String> {
+        let buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&buffer)
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Forgets the lines written so far.
+    pub fn clear(&self) {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+impl Write for CapturedLines {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use diesel::sqlite::SqliteConnection;
+
+    use super::*;
+    use crate::{DbLogMode, QueryKind};
+
+    fn connection(mode: DbLogMode) -> LoggingConnection<SqliteConnection> {
+        LoggingConnection::new(SqliteConnection::establish(":memory:").unwrap(), mode)
+    }
+
+    #[test]
+    fn capture_sink_collects_every_query() {
+        let (mut conn, sink) = CaptureSink::attach(connection(DbLogMode::NoLog));
+        conn.set_time_source(Arc::new(FixedClock(UNIX_EPOCH)));
+        conn.execute("CREATE TABLE t (id INTEGER)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        let events = sink.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].kind, QueryKind::Insert);
+        assert_eq!(events[1].rows, Some(2));
+        assert_eq!(events[1].start_time, UNIX_EPOCH);
+        sink.clear();
+        assert!(sink.events().is_empty());
+    }
+
+    #[test]
+    fn captured_lines_are_deterministic_with_a_fixed_clock() {
+        let run = || {
+            let mut conn = connection(DbLogMode::Standard);
+            let lines = CapturedLines::default();
+            conn.set_output(lines.output());
+            conn.set_time_source(Arc::new(FixedClock(UNIX_EPOCH)));
+            conn.set_duration_bucket(Some(Duration::from_secs(3600)));
+            conn.execute("SELECT 1").unwrap();
+            lines.lines()
+        };
+        let lines = run();
+        assert_eq!(lines, run());
+        assert_eq!(
+            lines,
+            ["[1970-01-01 00:00:00 UTC] DEBUG: [SELECT] Query ran in 0 µs: SELECT 1"]
+        );
+    }
+
+    #[test]
+    fn captured_lines_can_be_cleared() {
+        let mut conn = connection(DbLogMode::Excessive);
+        let lines = CapturedLines::default();
+        conn.set_output(lines.output());
+        conn.execute("SELECT 1").unwrap();
+        assert!(!lines.lines().is_empty());
+        lines.clear();
+        assert!(lines.lines().is_empty());
+    }
+}
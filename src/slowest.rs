@@ -0,0 +1,61 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The slowest query a `LoggingConnection` has run.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SlowQueryRecord {
+    /// The SQL of the query, as rendered by `diesel::debug_query`.
+    pub query: String,
+    /// How long the query took.
+    pub duration: Duration,
+    /// The wall-clock time at which the query was started.
+    pub start_time: std::time::SystemTime,
+}
+
+#[derive(Default)]
+pub(crate) struct SlowestQuery {
+    record: Mutex<Option<SlowQueryRecord>>,
+}
+
+impl SlowestQuery {
+    /// Replaces the record if the query took longer than the slowest one so far.
+    pub(crate) fn observe(
+        &self,
+        query: &str,
+        duration: Duration,
+        start_time: crate::clock::Timestamp,
+    ) {
+        let mut record = self.record();
+        if let Some(slowest) = record.as_ref() {
+            if slowest.duration >= duration {
+                return;
+            }
+        }
+        *record = Some(SlowQueryRecord {
+            query: query.to_owned(),
+            duration,
+            start_time: start_time.0,
+        });
+    }
+
+    /// Whether a query which took `duration` would replace the record.
+    pub(crate) fn is_slower(&self, duration: Duration) -> bool {
+        match self.record().as_ref() {
+            Some(slowest) => duration > slowest.duration,
+            None => true,
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<SlowQueryRecord> {
+        self.record().clone()
+    }
+
+    pub(crate) fn take(&self) -> Option<SlowQueryRecord> {
+        self.record().take()
+    }
+
+    fn record(&self) -> std::sync::MutexGuard<'_, Option<SlowQueryRecord>> {
+        self.record.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
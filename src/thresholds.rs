@@ -0,0 +1,224 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::DbLogMode;
+
+/// The durations above which a query counts as slow and as very slow.
+///
+/// Unless global defaults are set, every connection starts with the `Default`
+/// thresholds of 1 s and 5 s, whatever its backend. Databases which usually
+/// run close by can opt into tighter presets, e.g. with
+/// `conn.set_default_slow_query_thresholds(SlowQueryThresholds::postgres())`:
+///
+/// | preset     | slow   | very slow |
+/// |------------|--------|-----------|
+/// | `sqlite`   | 100 ms | 1 s       |
+/// | `postgres` | 500 ms | 2 s       |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowQueryThresholds {
+    /// Slow queries are logged at `info` in `Standard` mode, unless other
+    /// `LogLevels` are set, and at `warn` in the `Excessive` modes.
+    pub slow: Duration,
+    /// Very slow queries are logged at `warn` in `Standard` mode, unless other
+    /// `LogLevels` are set, and may be explained.
+    pub very_slow: Duration,
+}
+
+impl Default for SlowQueryThresholds {
+    fn default() -> Self {
+        SlowQueryThresholds {
+            slow: Duration::from_secs(1),
+            very_slow: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The levels queries are logged at in `Standard` mode, by the threshold
+/// they crossed. `Default` are `debug`, `info` and `warn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogLevels {
+    /// The level of queries below the slow threshold.
+    pub normal: log::Level,
+    /// The level of slow queries.
+    pub slow: log::Level,
+    /// The level of very slow queries, e.g. `error` to page someone.
+    /// Queries far above the threshold are still escalated to `error`.
+    pub very_slow: log::Level,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        LogLevels {
+            normal: log::Level::Debug,
+            slow: log::Level::Info,
+            very_slow: log::Level::Warn,
+        }
+    }
+}
+
+impl SlowQueryThresholds {
+    /// Thresholds for SQLite, which runs in process: 100 ms and 1 s.
+    pub fn sqlite() -> Self {
+        SlowQueryThresholds {
+            slow: Duration::from_millis(100),
+            very_slow: Duration::from_secs(1),
+        }
+    }
+
+    /// Thresholds for Postgres on the same network: 500 ms and 2 s.
+    pub fn postgres() -> Self {
+        SlowQueryThresholds {
+            slow: Duration::from_millis(500),
+            very_slow: Duration::from_secs(2),
+        }
+    }
+
+    pub(crate) fn is_slow(&self, duration: Duration) -> bool {
+        duration >= self.slow
+    }
+
+    pub(crate) fn is_very_slow(&self, duration: Duration) -> bool {
+        duration >= self.very_slow
+    }
+}
+
+/// Slow query thresholds which can be changed while queries run, e.g. tightened
+/// during an incident. Share one `Arc` between connections, e.g. through
+/// `set_global_defaults`, and every one of them sees a change with its next query.
+#[derive(Debug)]
+pub struct LiveThresholds {
+    slow: AtomicU64,
+    very_slow: AtomicU64,
+}
+
+impl LiveThresholds {
+    pub fn new(thresholds: SlowQueryThresholds) -> Self {
+        LiveThresholds {
+            slow: AtomicU64::new(to_nanos(thresholds.slow)),
+            very_slow: AtomicU64::new(to_nanos(thresholds.very_slow)),
+        }
+    }
+
+    pub fn set_slow_threshold(&self, slow: Duration) {
+        self.slow.store(to_nanos(slow), Ordering::Relaxed);
+    }
+
+    pub fn set_very_slow_threshold(&self, very_slow: Duration) {
+        self.very_slow.store(to_nanos(very_slow), Ordering::Relaxed);
+    }
+
+    /// The thresholds as of now.
+    pub fn get(&self) -> SlowQueryThresholds {
+        SlowQueryThresholds {
+            slow: Duration::from_nanos(self.slow.load(Ordering::Relaxed)),
+            very_slow: Duration::from_nanos(self.very_slow.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+fn to_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u128::from(u64::MAX)) as u64
+}
+
+/// The thresholds of every log mode, all of them the defaults at first.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ModeThresholds {
+    standard: SlowQueryThresholds,
+    verbose: SlowQueryThresholds,
+    excessive: SlowQueryThresholds,
+    excessive_mini: SlowQueryThresholds,
+    on_error: SlowQueryThresholds,
+    silent: SlowQueryThresholds,
+    profile: SlowQueryThresholds,
+    /// Replaces the thresholds of every mode but `Profile`, if set.
+    live: Option<Arc<LiveThresholds>>,
+}
+
+impl ModeThresholds {
+    pub(crate) fn set_all(&mut self, thresholds: SlowQueryThresholds) {
+        self.standard = thresholds;
+        self.verbose = thresholds;
+        self.excessive = thresholds;
+        self.excessive_mini = thresholds;
+        self.on_error = thresholds;
+        self.silent = thresholds;
+        self.profile = thresholds;
+    }
+
+    pub(crate) fn set_live(&mut self, live: Option<Arc<LiveThresholds>>) {
+        self.live = live;
+    }
+
+    pub(crate) fn get(&self, mode: DbLogMode) -> SlowQueryThresholds {
+        match &self.live {
+            // Profiles are compared offline, so their thresholds stay fixed.
+            Some(live) if mode != DbLogMode::Profile => return live.get(),
+            _ => {}
+        }
+        *match mode {
+            // Queries are only timed for other purposes in `NoLog` mode.
+            DbLogMode::NoLog | DbLogMode::Standard => &self.standard,
+            DbLogMode::Verbose => &self.verbose,
+            DbLogMode::Excessive => &self.excessive,
+            DbLogMode::ExcessiveMini => &self.excessive_mini,
+            DbLogMode::OnError => &self.on_error,
+            DbLogMode::Silent => &self.silent,
+            DbLogMode::Profile => &self.profile,
+        }
+    }
+
+    pub(crate) fn set(&mut self, mode: DbLogMode, thresholds: SlowQueryThresholds) {
+        match mode {
+            DbLogMode::NoLog | DbLogMode::Standard => self.standard = thresholds,
+            DbLogMode::Verbose => self.verbose = thresholds,
+            DbLogMode::Excessive => self.excessive = thresholds,
+            DbLogMode::ExcessiveMini => self.excessive_mini = thresholds,
+            DbLogMode::OnError => self.on_error = thresholds,
+            DbLogMode::Silent => self.silent = thresholds,
+            DbLogMode::Profile => self.profile = thresholds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds(slow_ms: u64) -> SlowQueryThresholds {
+        SlowQueryThresholds {
+            slow: Duration::from_millis(slow_ms),
+            very_slow: Duration::from_millis(slow_ms * 10),
+        }
+    }
+
+    #[test]
+    fn profile_has_its_own_thresholds() {
+        let mut modes = ModeThresholds::default();
+        modes.set_all(thresholds(100));
+        modes.set(DbLogMode::Profile, thresholds(5));
+        assert_eq!(modes.get(DbLogMode::Profile), thresholds(5));
+        assert_eq!(modes.get(DbLogMode::Standard), thresholds(100));
+        modes.set(DbLogMode::Standard, thresholds(20));
+        assert_eq!(modes.get(DbLogMode::Profile), thresholds(5));
+        assert_eq!(modes.get(DbLogMode::NoLog), thresholds(20));
+    }
+
+    #[test]
+    fn live_thresholds_leave_profile_alone() {
+        let mut modes = ModeThresholds::default();
+        modes.set(DbLogMode::Profile, thresholds(5));
+        let live = Arc::new(LiveThresholds::new(thresholds(100)));
+        modes.set_live(Some(live.clone()));
+        live.set_slow_threshold(Duration::from_millis(300));
+        assert_eq!(
+            modes.get(DbLogMode::Standard).slow,
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            modes.get(DbLogMode::Verbose).slow,
+            Duration::from_millis(300)
+        );
+        assert_eq!(modes.get(DbLogMode::Profile), thresholds(5));
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A limit for `set_max_logs_per_sec` which only kicks in during incidents.
+pub const DEFAULT_MAX_LOGS_PER_SEC: u32 = 1000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static THROTTLE: Mutex<Option<Throttle>> = Mutex::new(None);
+
+/// Limits the lines every `LoggingConnection` of the process emits together to
+/// `max_per_sec` per second, with bursts of up to one second worth of lines,
+/// e.g. `set_max_logs_per_sec(Some(DEFAULT_MAX_LOGS_PER_SEC))`. This protects
+/// the log infrastructure when a thundering herd of slow queries hits.
+///
+/// Once the budget runs low, lines at `debug` and `trace` are dropped first,
+/// then lines at `info`, so warnings about slow queries and errors get the
+/// rest of it. The number of dropped lines is logged at `warn` at most once per
+/// second, with the next line which is emitted.
+///
+/// Pass `None` to emit every line again, which is the default.
+pub fn set_max_logs_per_sec(max_per_sec: Option<u32>) {
+    let mut throttle = THROTTLE.lock().unwrap_or_else(|e| e.into_inner());
+    *throttle = max_per_sec.map(Throttle::new);
+    ENABLED.store(throttle.is_some(), Ordering::Relaxed);
+}
+
+/// Decides whether a line at `level` is emitted. Also returns the summary of
+/// the lines dropped since the last summary, if it is due.
+pub(crate) fn admit(level: log::Level) -> (bool, Option<String>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return (true, None);
+    }
+    let mut throttle = THROTTLE.lock().unwrap_or_else(|e| e.into_inner());
+    match throttle.as_mut() {
+        Some(throttle) => throttle.admit(level, Instant::now()),
+        None => (true, None),
+    }
+}
+
+/// A token bucket which holds at most one second worth of lines.
+struct Throttle {
+    max_per_sec: u32,
+    tokens: f64,
+    refilled: Instant,
+    dropped: u64,
+    summarized: Instant,
+}
+
+impl Throttle {
+    fn new(max_per_sec: u32) -> Self {
+        let now = Instant::now();
+        Throttle {
+            max_per_sec,
+            tokens: f64::from(max_per_sec),
+            refilled: now,
+            dropped: 0,
+            summarized: now,
+        }
+    }
+
+    fn admit(&mut self, level: log::Level, now: Instant) -> (bool, Option<String>) {
+        let capacity = f64::from(self.max_per_sec);
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * capacity).min(capacity);
+        self.refilled = now;
+
+        // The part of the budget lower levels leave for higher ones.
+        let reserve = match level {
+            log::Level::Error | log::Level::Warn => 0.0,
+            log::Level::Info => capacity / 4.0,
+            log::Level::Debug | log::Level::Trace => capacity / 2.0,
+        };
+        let admitted = self.tokens - 1.0 >= reserve;
+        if admitted {
+            self.tokens -= 1.0;
+        } else {
+            self.dropped += 1;
+        }
+
+        let summary = if self.dropped > 0
+            && now.saturating_duration_since(self.summarized) >= Duration::from_secs(1)
+        {
+            let summary = format!(
+                "Dropped {} log lines to stay under {} lines per second",
+                self.dropped, self.max_per_sec
+            );
+            self.dropped = 0;
+            self.summarized = now;
+            Some(summary)
+        } else {
+            None
+        };
+        (admitted, summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn lower_levels_leave_part_of_the_budget_to_higher_ones() {
+        let mut throttle = Throttle::new(8);
+        let now = throttle.refilled;
+        let admitted = |throttle: &mut Throttle, level| throttle.admit(level, now).0;
+        for _ in 0..4 {
+            assert!(admitted(&mut throttle, Level::Debug));
+        }
+        assert!(!admitted(&mut throttle, Level::Debug));
+        for _ in 0..2 {
+            assert!(admitted(&mut throttle, Level::Info));
+        }
+        assert!(!admitted(&mut throttle, Level::Info));
+        for _ in 0..2 {
+            assert!(admitted(&mut throttle, Level::Warn));
+        }
+        assert!(!admitted(&mut throttle, Level::Error));
+    }
+
+    #[test]
+    fn dropped_lines_are_summarized_once_the_budget_is_refilled() {
+        let mut throttle = Throttle::new(1);
+        let start = throttle.refilled;
+        assert_eq!(throttle.admit(Level::Warn, start), (true, None));
+        assert_eq!(throttle.admit(Level::Warn, start), (false, None));
+        assert_eq!(throttle.admit(Level::Error, start), (false, None));
+        assert_eq!(
+            throttle.admit(Level::Warn, start + Duration::from_secs(1)),
+            (
+                true,
+                Some(String::from(
+                    "Dropped 2 log lines to stay under 1 lines per second"
+                ))
+            )
+        );
+        assert_eq!(
+            throttle.admit(Level::Warn, start + Duration::from_secs(2)),
+            (true, None)
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::SlowQueryThresholds;
+
+/// Whether a `Connection` method reads rows or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// `load` and the other `query_by_*` methods.
+    Read,
+    /// `execute`, `execute_returning_count` and `batch_execute`.
+    Write,
+}
+
+/// The cumulative time spent in reads and in writes, in nanoseconds,
+/// and the number of queries, slow ones and very slow ones.
+#[derive(Default)]
+pub(crate) struct TotalTime {
+    read: AtomicU64,
+    write: AtomicU64,
+    queries: AtomicU64,
+    slow: AtomicU64,
+    very_slow: AtomicU64,
+}
+
+impl TotalTime {
+    pub(crate) fn add(&self, access: Access, duration: Duration, thresholds: &SlowQueryThresholds) {
+        let nanos = duration.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.counter(access).fetch_add(nanos, Ordering::Relaxed);
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        if thresholds.is_slow(duration) {
+            self.slow.fetch_add(1, Ordering::Relaxed);
+        }
+        if thresholds.is_very_slow(duration) {
+            self.very_slow.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn get(&self, access: Access) -> Duration {
+        Duration::from_nanos(self.counter(access).load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn total(&self) -> Duration {
+        self.get(Access::Read) + self.get(Access::Write)
+    }
+
+    /// Returns the total time and starts counting it from zero.
+    pub(crate) fn take_total(&self) -> Duration {
+        let read = self.read.swap(0, Ordering::Relaxed);
+        let write = self.write.swap(0, Ordering::Relaxed);
+        Duration::from_nanos(read) + Duration::from_nanos(write)
+    }
+
+    pub(crate) fn queries(&self) -> u64 {
+        self.queries.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn slow_queries(&self) -> u64 {
+        self.slow.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn very_slow_queries(&self) -> u64 {
+        self.very_slow.load(Ordering::Relaxed)
+    }
+
+    fn counter(&self, access: Access) -> &AtomicU64 {
+        match access {
+            Access::Read => &self.read,
+            Access::Write => &self.write,
+        }
+    }
+}
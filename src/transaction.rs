@@ -0,0 +1,372 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buffers the SQL of every statement run inside the open transactions of a
+/// connection, so the statements can be dumped if a transaction is rolled back.
+///
+/// There is one frame per transaction depth. Committing a savepoint merges its
+/// statements into the enclosing transaction, because the outer transaction may
+/// still fail. Committing the outermost transaction discards everything.
+#[derive(Default)]
+pub(crate) struct TransactionBuffer {
+    limit: Option<usize>,
+    frames: Mutex<Vec<BufferedStatements>>,
+}
+
+/// The statements buffered for one transaction depth.
+#[derive(Default)]
+pub(crate) struct BufferedStatements {
+    pub(crate) statements: Vec<String>,
+    /// Statements which were not buffered because the limit was reached.
+    pub(crate) omitted: usize,
+}
+
+impl TransactionBuffer {
+    /// Enables buffering of at most `limit` statements per transaction,
+    /// or disables it with `None`.
+    pub(crate) fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        if limit.is_none() {
+            self.frames().clear();
+        }
+    }
+
+    /// Returns true if statements run right now would be buffered.
+    pub(crate) fn is_recording(&self) -> bool {
+        self.limit.is_some() && !self.frames().is_empty()
+    }
+
+    pub(crate) fn begin(&self) {
+        if self.limit.is_some() {
+            self.frames().push(BufferedStatements::default());
+        }
+    }
+
+    pub(crate) fn commit(&self) {
+        let mut frames = self.frames();
+        if let Some(frame) = frames.pop() {
+            if let Some(parent) = frames.last_mut() {
+                parent.statements.extend(frame.statements);
+                parent.omitted += frame.omitted;
+            }
+        }
+    }
+
+    /// Removes the innermost frame and returns it for logging.
+    pub(crate) fn rollback(&self) -> Option<BufferedStatements> {
+        self.frames().pop()
+    }
+
+    pub(crate) fn record(&self, query: String) {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut frames = self.frames();
+        let buffered = frames.iter().map(|f| f.statements.len()).sum::<usize>();
+        if let Some(frame) = frames.last_mut() {
+            if buffered < limit {
+                frame.statements.push(query);
+            } else {
+                frame.omitted += 1;
+            }
+        }
+    }
+
+    fn frames(&self) -> std::sync::MutexGuard<'_, Vec<BufferedStatements>> {
+        // A poisoned buffer only holds log lines, so keep using it.
+        self.frames.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Dumps the statements of a rolled back transaction at `warn`.
+pub(crate) fn log_rolled_back(buffered: &BufferedStatements) {
+    if buffered.statements.is_empty() && buffered.omitted == 0 {
+        return;
+    }
+    let mut message = format!(
+        "Transaction rolled back after {} statement(s):",
+        buffered.statements.len() + buffered.omitted
+    );
+    for query in &buffered.statements {
+        message.push_str("\n    ");
+        message.push_str(query);
+    }
+    if buffered.omitted > 0 {
+        message.push_str(&format!(
+            "\n    ... {} more statement(s) not buffered",
+            buffered.omitted
+        ));
+    }
+    emit_log!(log::Level::Warn, "{}", message);
+}
+
+/// Counts the queries run inside the open transactions of a connection,
+/// one counter per transaction depth.
+#[derive(Default)]
+pub(crate) struct TransactionQueryCounter {
+    limit: Option<usize>,
+    counts: Mutex<Vec<usize>>,
+}
+
+impl TransactionQueryCounter {
+    pub(crate) fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        if limit.is_none() {
+            self.counts().clear();
+        }
+    }
+
+    pub(crate) fn begin(&self) {
+        if self.limit.is_some() {
+            self.counts().push(0);
+        }
+    }
+
+    pub(crate) fn increment(&self) {
+        if self.limit.is_some() {
+            if let Some(count) = self.counts().last_mut() {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Pops the innermost counter and warns if the committed transaction
+    /// ran more queries than allowed. Queries of a committed savepoint
+    /// count towards the enclosing transaction.
+    pub(crate) fn commit(&self) {
+        let mut counts = self.counts();
+        let count = match counts.pop() {
+            Some(count) => count,
+            None => return,
+        };
+        match (counts.last_mut(), self.limit) {
+            (Some(parent), _) => *parent += count,
+            (None, Some(limit)) if count > limit => {
+                emit_log!(
+                    log::Level::Warn,
+                    "Transaction committed after running {} queries, more than the limit of {}",
+                    count,
+                    limit
+                );
+            }
+            (None, _) => {}
+        }
+    }
+
+    pub(crate) fn rollback(&self) {
+        self.counts().pop();
+    }
+
+    fn counts(&self) -> std::sync::MutexGuard<'_, Vec<usize>> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Measures how long the outermost transaction of a connection is open.
+#[derive(Default)]
+pub(crate) struct TransactionTimer {
+    started: Mutex<Option<Instant>>,
+}
+
+impl TransactionTimer {
+    pub(crate) fn start(&self) {
+        *self.started() = Some(Instant::now());
+    }
+
+    /// Returns how long the transaction was open, if it was started.
+    pub(crate) fn stop(&self) -> Option<Duration> {
+        self.started().take().map(|started| started.elapsed())
+    }
+
+    fn started(&self) -> std::sync::MutexGuard<'_, Option<Instant>> {
+        self.started.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The statistics of the queries run inside one transaction depth.
+struct TransactionStats {
+    started: Instant,
+    queries: usize,
+    query_time: Duration,
+    slowest: Duration,
+}
+
+/// Collects the statistics summarized in one line per committed transaction,
+/// one frame per transaction depth.
+#[derive(Default)]
+pub(crate) struct TransactionSummaries {
+    enabled: bool,
+    frames: Mutex<Vec<TransactionStats>>,
+}
+
+impl TransactionSummaries {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.frames().clear();
+        }
+    }
+
+    pub(crate) fn begin(&self) {
+        if self.enabled {
+            self.frames().push(TransactionStats {
+                started: Instant::now(),
+                queries: 0,
+                query_time: Duration::default(),
+                slowest: Duration::default(),
+            });
+        }
+    }
+
+    pub(crate) fn record(&self, duration: Duration) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(stats) = self.frames().last_mut() {
+            stats.queries += 1;
+            stats.query_time += duration;
+            stats.slowest = stats.slowest.max(duration);
+        }
+    }
+
+    /// Pops the innermost frame and returns the summary line if the outermost
+    /// transaction was committed. The queries of a committed savepoint count
+    /// towards the enclosing transaction.
+    pub(crate) fn commit(&self) -> Option<String> {
+        let mut frames = self.frames();
+        let stats = frames.pop()?;
+        match frames.last_mut() {
+            Some(parent) => {
+                parent.queries += stats.queries;
+                parent.query_time += stats.query_time;
+                parent.slowest = parent.slowest.max(stats.slowest);
+                None
+            }
+            None => Some(format!(
+                "Transaction committed: queries={} total_query_ms={:.1} tx_wall_ms={:.1} slowest_ms={:.1}",
+                stats.queries,
+                stats.query_time.as_secs_f64() * 1000.0,
+                stats.started.elapsed().as_secs_f64() * 1000.0,
+                stats.slowest.as_secs_f64() * 1000.0
+            )),
+        }
+    }
+
+    pub(crate) fn rollback(&self) {
+        self.frames().pop();
+    }
+
+    fn frames(&self) -> std::sync::MutexGuard<'_, Vec<TransactionStats>> {
+        self.frames.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(limit: usize) -> TransactionBuffer {
+        let mut buffer = TransactionBuffer::default();
+        buffer.set_limit(Some(limit));
+        buffer
+    }
+
+    #[test]
+    fn rolled_back_savepoints_return_only_their_statements() {
+        let buffer = limited(10);
+        buffer.begin();
+        buffer.record("INSERT 1".to_owned());
+        buffer.begin();
+        buffer.record("INSERT 2".to_owned());
+        let savepoint = buffer.rollback().unwrap();
+        assert_eq!(savepoint.statements, ["INSERT 2"]);
+        let transaction = buffer.rollback().unwrap();
+        assert_eq!(transaction.statements, ["INSERT 1"]);
+        assert!(!buffer.is_recording());
+    }
+
+    #[test]
+    fn committed_savepoints_merge_into_the_enclosing_transaction() {
+        let buffer = limited(10);
+        buffer.begin();
+        buffer.record("INSERT 1".to_owned());
+        buffer.begin();
+        buffer.record("INSERT 2".to_owned());
+        buffer.commit();
+        assert_eq!(
+            buffer.rollback().unwrap().statements,
+            ["INSERT 1", "INSERT 2"]
+        );
+    }
+
+    #[test]
+    fn statements_over_the_limit_are_counted() {
+        let buffer = limited(2);
+        buffer.begin();
+        buffer.record("INSERT 1".to_owned());
+        buffer.begin();
+        for n in 2..5 {
+            buffer.record(format!("INSERT {}", n));
+        }
+        buffer.commit();
+        let rolled_back = buffer.rollback().unwrap();
+        assert_eq!(rolled_back.statements, ["INSERT 1", "INSERT 2"]);
+        assert_eq!(rolled_back.omitted, 2);
+    }
+
+    #[test]
+    fn nothing_is_buffered_without_a_limit_or_a_transaction() {
+        let buffer = TransactionBuffer::default();
+        buffer.begin();
+        buffer.record("INSERT 1".to_owned());
+        assert!(!buffer.is_recording());
+        assert!(buffer.rollback().is_none());
+
+        let buffer = limited(10);
+        buffer.record("INSERT 1".to_owned());
+        buffer.begin();
+        buffer.commit();
+        assert!(buffer.rollback().is_none());
+    }
+
+    fn summaries() -> TransactionSummaries {
+        let mut summaries = TransactionSummaries::default();
+        summaries.set_enabled(true);
+        summaries
+    }
+
+    #[test]
+    fn committed_transactions_are_summarized_with_their_savepoints() {
+        let summaries = summaries();
+        summaries.begin();
+        summaries.record(Duration::from_millis(2));
+        summaries.begin();
+        summaries.record(Duration::from_millis(5));
+        assert_eq!(summaries.commit(), None);
+        summaries.begin();
+        summaries.record(Duration::from_millis(100));
+        summaries.rollback();
+        let summary = summaries.commit().unwrap();
+        assert!(
+            summary.starts_with("Transaction committed: queries=2 total_query_ms=7.0 tx_wall_ms="),
+            "{}",
+            summary
+        );
+        assert!(summary.ends_with(" slowest_ms=5.0"), "{}", summary);
+    }
+
+    #[test]
+    fn nothing_is_summarized_when_disabled_or_rolled_back() {
+        let summaries = summaries();
+        summaries.begin();
+        summaries.record(Duration::from_millis(1));
+        summaries.rollback();
+        assert_eq!(summaries.commit(), None);
+
+        let summaries = TransactionSummaries::default();
+        summaries.begin();
+        summaries.record(Duration::from_millis(1));
+        assert_eq!(summaries.commit(), None);
+    }
+}
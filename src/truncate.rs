@@ -0,0 +1,60 @@
+//! Every place which shortens logged text goes through these functions,
+//! so no text is ever sliced in the middle of a multibyte character.
+
+use std::borrow::Cow;
+
+/// Cuts `text` down to its first `max_chars` chars.
+pub(crate) fn truncate_chars(text: &str, max_chars: usize) -> Cow<'_, str> {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => Cow::Borrowed(&text[..end]),
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Cuts `text` down to at most `max_bytes` bytes and appends `…` if anything
+/// was cut off. The cut is moved back to the nearest char boundary,
+/// so multibyte characters are never split.
+pub(crate) fn truncate_bytes(text: &str, max_bytes: usize) -> Cow<'_, str> {
+    if text.len() <= max_bytes {
+        return Cow::Borrowed(text);
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}…", &text[..end]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_borrowed_unchanged() {
+        assert!(matches!(
+            truncate_bytes("SELECT 1", 8),
+            Cow::Borrowed("SELECT 1")
+        ));
+    }
+
+    #[test]
+    fn cuts_are_moved_back_to_a_char_boundary() {
+        // `ö` takes two bytes and `✓` three.
+        assert_eq!(truncate_bytes("größe", 3), "gr…");
+        assert_eq!(truncate_bytes("größe", 4), "grö…");
+        assert_eq!(truncate_bytes("✓✓", 5), "✓…");
+        assert_eq!(truncate_bytes("✓", 2), "…");
+        assert_eq!(truncate_bytes("SELECT 1", 0), "…");
+    }
+
+    #[test]
+    fn chars_are_counted_not_bytes() {
+        assert_eq!(truncate_chars("größe", 3), "grö");
+        assert_eq!(truncate_chars("✓✓", 2), "✓✓");
+        assert_eq!(truncate_chars("✓✓", 0), "");
+        assert!(matches!(
+            truncate_chars("SELECT", 10),
+            Cow::Borrowed("SELECT")
+        ));
+    }
+}
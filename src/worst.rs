@@ -0,0 +1,166 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The slowest individual queries of the current window of the worst queries
+/// digest, see `LoggingConnection::set_worst_queries_digest`.
+#[derive(Default)]
+pub(crate) struct WorstQueries {
+    window: Mutex<Option<Window>>,
+}
+
+struct Window {
+    started: Instant,
+    queries: usize,
+    /// The slowest queries so far, the fastest of them on top.
+    slowest: BinaryHeap<Reverse<WorstQuery>>,
+}
+
+struct WorstQuery {
+    duration: Duration,
+    seq: u64,
+    query: String,
+    start_time: crate::clock::Timestamp,
+}
+
+impl PartialEq for WorstQuery {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for WorstQuery {}
+
+impl PartialOrd for WorstQuery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WorstQuery {
+    /// Slower queries are greater, and of equally slow ones the earlier one.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.duration
+            .cmp(&other.duration)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl WorstQueries {
+    /// Keeps the query if it is one of the `top` slowest of the window. Returns
+    /// the digest of the previous window instead if `interval` has passed since
+    /// it started, and starts a new one with this query.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn observe(
+        &self,
+        top: usize,
+        interval: Duration,
+        query: &str,
+        seq: u64,
+        duration: Duration,
+        start_time: crate::clock::Timestamp,
+        precision: Option<usize>,
+    ) -> Option<String> {
+        let now = Instant::now();
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let digest = match window.as_ref() {
+            Some(current) if now.saturating_duration_since(current.started) >= interval => {
+                window.take().map(|ended| ended.digest(interval, precision))
+            }
+            _ => None,
+        };
+        let window = window.get_or_insert_with(|| Window {
+            started: now,
+            queries: 0,
+            slowest: BinaryHeap::with_capacity(top + 1),
+        });
+        window.queries += 1;
+        let is_slower = match window.slowest.peek() {
+            Some(Reverse(fastest)) if window.slowest.len() >= top => duration > fastest.duration,
+            _ => top > 0,
+        };
+        if is_slower {
+            window.slowest.push(Reverse(WorstQuery {
+                duration,
+                seq,
+                query: query.to_owned(),
+                start_time,
+            }));
+            if window.slowest.len() > top {
+                window.slowest.pop();
+            }
+        }
+        digest
+    }
+}
+
+impl Window {
+    /// Renders the slowest queries of the window, the slowest first.
+    fn digest(self, interval: Duration, precision: Option<usize>) -> String {
+        let slowest = self.slowest.into_sorted_vec();
+        let mut digest = format!(
+            "Worst queries, slowest {} of {} queries in the last {}:",
+            slowest.len(),
+            self.queries,
+            crate::format_duration_auto(interval, precision)
+        );
+        for Reverse(query) in slowest {
+            digest.push_str(&format!(
+                "\n    {} at {}: {}",
+                crate::format_duration_auto(query.duration, precision),
+                query.start_time,
+                query.query
+            ));
+        }
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::clock::Timestamp;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn observe(worst: &WorstQueries, interval: Duration, seq: u64, millis: u64) -> Option<String> {
+        let query = format!("SELECT {}", seq);
+        let duration = Duration::from_millis(millis);
+        worst.observe(
+            2,
+            interval,
+            &query,
+            seq,
+            duration,
+            Timestamp(UNIX_EPOCH),
+            None,
+        )
+    }
+
+    #[test]
+    fn the_digest_lists_the_slowest_queries_first() {
+        let worst = WorstQueries::default();
+        for (seq, millis) in [(1, 5), (2, 30), (3, 10), (4, 30), (5, 1)].iter() {
+            assert_eq!(observe(&worst, HOUR, *seq, *millis), None);
+        }
+        let window = worst.window.lock().unwrap().take().unwrap();
+        assert_eq!(
+            window.digest(HOUR, None),
+            "Worst queries, slowest 2 of 5 queries in the last 3600.00 s:\n    \
+             30.0 ms at 1970-01-01 00:00:00 UTC: SELECT 2\n    \
+             30.0 ms at 1970-01-01 00:00:00 UTC: SELECT 4"
+        );
+    }
+
+    #[test]
+    fn the_digest_is_returned_once_the_interval_passed() {
+        let worst = WorstQueries::default();
+        assert_eq!(observe(&worst, Duration::from_secs(0), 1, 5), None);
+        let digest = observe(&worst, Duration::from_secs(0), 2, 7).unwrap();
+        assert!(digest.starts_with("Worst queries, slowest 1 of 1 queries "));
+        assert!(digest.ends_with(": SELECT 1"), "{}", digest);
+    }
+}
@@ -0,0 +1,134 @@
+//! The callbacks which run for the queries of a connection.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel_logger::{
+    CallbackSink, DbLogMode, FileSink, QueryEvent, QueryKind, SlowQueryCallback,
+    SlowQueryThresholds,
+};
+
+use common::connection;
+
+/// Collects the SQL of every event a callback is called with.
+fn recorder() -> (Arc<Mutex<Vec<String>>>, SlowQueryCallback) {
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let recorded = queries.clone();
+    let callback = Arc::new(move |event: &QueryEvent| {
+        recorded.lock().unwrap().push(event.query.clone());
+    });
+    (queries, callback)
+}
+
+fn thresholds(slow: Duration) -> SlowQueryThresholds {
+    SlowQueryThresholds {
+        slow,
+        very_slow: Duration::from_secs(60),
+    }
+}
+
+#[test]
+fn the_slow_query_callback_only_sees_slow_queries() {
+    let (mut conn, _) = connection(DbLogMode::NoLog);
+    let (queries, callback) = recorder();
+    conn.set_on_slow_query(Some(callback));
+
+    conn.set_default_slow_query_thresholds(thresholds(Duration::from_secs(60)));
+    conn.execute("SELECT 1").unwrap();
+    assert!(queries.lock().unwrap().is_empty());
+
+    conn.set_default_slow_query_thresholds(thresholds(Duration::from_nanos(1)));
+    conn.execute("SELECT 2").unwrap();
+    assert_eq!(*queries.lock().unwrap(), ["SELECT 2"]);
+}
+
+#[test]
+fn every_sink_gets_every_event() {
+    let (mut conn, lines) = connection(DbLogMode::NoLog);
+    let path = std::env::temp_dir().join(format!("diesel-logger-sinks-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (first, first_callback) = recorder();
+    let (second, second_callback) = recorder();
+    conn.add_sink(Arc::new(CallbackSink(move |event: &QueryEvent| {
+        first_callback(event)
+    })));
+    conn.add_sink(Arc::new(CallbackSink(move |event: &QueryEvent| {
+        second_callback(event)
+    })));
+    conn.add_sink(Arc::new(FileSink::open(&path).unwrap()));
+
+    conn.execute("CREATE TABLE t (a INTEGER)").unwrap();
+    conn.execute("INSERT INTO t VALUES (1)").unwrap();
+    assert!(conn.execute("INSERT INTO missing VALUES (1)").is_err());
+
+    let queries = [
+        "CREATE TABLE t (a INTEGER)",
+        "INSERT INTO t VALUES (1)",
+        "INSERT INTO missing VALUES (1)",
+    ];
+    assert_eq!(*first.lock().unwrap(), queries);
+    assert_eq!(*second.lock().unwrap(), queries);
+    let file = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let file: Vec<_> = file.lines().collect();
+    assert_eq!(file.len(), 3, "{:#?}", file);
+    assert!(file[1].contains("] #2 insert "), "{:#?}", file);
+    assert!(
+        file[1].ends_with(" rows=1: INSERT INTO t VALUES (1)"),
+        "{:#?}",
+        file
+    );
+    assert_eq!(lines.text(), "");
+}
+
+#[test]
+fn the_slow_query_classifier_overrides_the_thresholds() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    let (queries, callback) = recorder();
+    conn.set_on_slow_query(Some(callback));
+    conn.set_default_slow_query_thresholds(thresholds(Duration::from_nanos(1)));
+    conn.set_slow_classifier(Some(Arc::new(|event: &QueryEvent| {
+        event.kind == QueryKind::Insert
+    })));
+
+    conn.execute("CREATE TABLE t (a INTEGER)").unwrap();
+    assert!(!conn.last_query_was_slow());
+    conn.execute("INSERT INTO t VALUES (1)").unwrap();
+    assert!(conn.last_query_was_slow());
+
+    assert_eq!(*queries.lock().unwrap(), ["INSERT INTO t VALUES (1)"]);
+    let levels: Vec<_> = lines
+        .all()
+        .iter()
+        .map(|line| line[..line.find(':').unwrap()].to_owned())
+        .collect();
+    assert_eq!(levels, ["DEBUG", "INFO"]);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn slow_queries_are_sent_to_the_channel() {
+    let (mut conn, _) = connection(DbLogMode::NoLog);
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    conn.set_slow_query_sender(Some(sender));
+
+    conn.set_default_slow_query_thresholds(thresholds(Duration::from_secs(60)));
+    conn.execute("SELECT 1").unwrap();
+    conn.set_default_slow_query_thresholds(thresholds(Duration::from_nanos(1)));
+    conn.execute("SELECT 2").unwrap();
+    conn.execute("SELECT 3").unwrap();
+
+    let mut queries = Vec::new();
+    while let Ok(event) = receiver.try_recv() {
+        assert!(event.slow);
+        queries.push(event.query);
+    }
+    assert_eq!(queries, ["SELECT 2", "SELECT 3"]);
+
+    // Queries after the receiver is gone still run.
+    drop(receiver);
+    conn.execute("SELECT 4").unwrap();
+}
@@ -0,0 +1,49 @@
+#![allow(dead_code)]
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_logger::{DbLogMode, LogOutput, LoggingConnection};
+
+/// The output of a connection, shared with the test.
+#[derive(Clone, Default)]
+pub struct Lines(Arc<Mutex<Vec<u8>>>);
+
+impl Lines {
+    /// Everything written so far.
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// Every line written so far, oldest first.
+    pub fn all(&self) -> Vec<String> {
+        self.text().lines().map(str::to_owned).collect()
+    }
+
+    /// Forgets the lines written so far.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+impl Write for Lines {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An in-memory SQLite connection which writes its lines without timestamps.
+pub fn connection(mode: DbLogMode) -> (LoggingConnection<SqliteConnection>, Lines) {
+    let mut conn = LoggingConnection::new(SqliteConnection::establish(":memory:").unwrap(), mode);
+    let lines = Lines::default();
+    conn.set_output(Arc::new(LogOutput::writer(lines.clone())));
+    conn.set_show_timestamp(false);
+    (conn, lines)
+}
@@ -0,0 +1,146 @@
+//! Every public way diesel 1.4 runs a query has to produce a line.
+
+// The `table!` and derive macros of diesel 1.4 implement traits inside functions.
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use diesel_logger::DbLogMode;
+
+use common::connection;
+
+table! {
+    t (id) {
+        id -> Integer,
+    }
+}
+
+#[test]
+fn loading_methods_are_logged() {
+    let (conn, lines) = connection(DbLogMode::Verbose);
+    let query = || diesel::select(sql::<Integer>("42"));
+
+    let _: i32 = query().get_result(&conn).unwrap();
+    let _: Vec<i32> = query().get_results(&conn).unwrap();
+    let _: Vec<i32> = query().load(&conn).unwrap();
+    let _: i32 = query().first(&conn).unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 4, "{:#?}", lines);
+    assert!(
+        lines.iter().all(|line| line.contains("SELECT 42")),
+        "{:#?}",
+        lines
+    );
+}
+
+#[test]
+fn sql_query_is_logged() {
+    #[derive(QueryableByName)]
+    struct Answer {
+        #[sql_type = "Integer"]
+        answer: i32,
+    }
+
+    let (conn, lines) = connection(DbLogMode::Verbose);
+    let answers: Vec<Answer> = diesel::sql_query("SELECT 42 AS answer")
+        .load(&conn)
+        .unwrap();
+    assert_eq!(answers[0].answer, 42);
+    diesel::sql_query("CREATE TABLE t (id INTEGER)")
+        .execute(&conn)
+        .unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 2, "{:#?}", lines);
+    assert!(lines[0].contains("SELECT 42 AS answer"), "{:#?}", lines);
+    assert!(lines[1].contains("CREATE TABLE t"), "{:#?}", lines);
+}
+
+#[test]
+fn load_timed_returns_the_duration_the_query_is_logged_with() {
+    let (conn, lines) = connection(DbLogMode::Verbose);
+    let (rows, duration): (Vec<i32>, _) = conn
+        .load_timed(diesel::select(sql::<Integer>("42")))
+        .unwrap();
+
+    assert_eq!(rows, vec![42]);
+    // Both come from the one measurement around the query.
+    assert_eq!(duration, conn.total_query_time());
+    assert_eq!(lines.all().len(), 1);
+}
+
+#[test]
+fn executed_queries_are_logged() {
+    let (conn, lines) = connection(DbLogMode::Verbose);
+    conn.batch_execute("CREATE TABLE t (id INTEGER PRIMARY KEY)")
+        .unwrap();
+    diesel::insert_into(t::table)
+        .values(t::id.eq(1))
+        .execute(&conn)
+        .unwrap();
+    assert_eq!(conn.execute("UPDATE t SET id = 2").unwrap(), 1);
+    assert_eq!(diesel::delete(t::table).execute(&conn).unwrap(), 1);
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 4, "{:#?}", lines);
+    assert!(lines[0].contains("CREATE TABLE t"), "{:#?}", lines);
+    assert!(
+        lines[1].contains("INSERT INTO `t` (`id`) VALUES (?)"),
+        "{:#?}",
+        lines
+    );
+    assert!(lines[2].contains("UPDATE t SET id = 2"), "{:#?}", lines);
+    assert!(lines[3].contains("DELETE FROM `t`"), "{:#?}", lines);
+}
+
+table! {
+    person (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+#[derive(Identifiable, AsChangeset, Queryable, Debug, PartialEq)]
+#[table_name = "person"]
+struct Person {
+    id: i32,
+    name: String,
+}
+
+#[test]
+fn update_and_fetch_is_logged_once() {
+    let (mut conn, lines) = connection(DbLogMode::Verbose);
+    conn.batch_execute(
+        "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+         INSERT INTO person VALUES (1, 'Ada')",
+    )
+    .unwrap();
+    conn.set_refuse_missing_where(true);
+    lines.clear();
+
+    let renamed = Person {
+        id: 1,
+        name: "Grace".to_owned(),
+    };
+    let person: Person = renamed.save_changes(&conn).unwrap();
+    assert_eq!(person, renamed);
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 1, "{:#?}", lines);
+    assert!(lines[0].contains("[UPDATE] Query ran in"), "{:#?}", lines);
+    assert!(
+        lines[0].ends_with(
+            r#": UPDATE `person` SET `name` = ? WHERE `person`.`id` = ? -- binds: ["Grace", 1]"#
+        ),
+        "{:#?}",
+        lines
+    );
+}
@@ -0,0 +1,37 @@
+//! The global defaults of new connections, in their own process as they are
+//! shared by every connection.
+
+mod common;
+
+use std::sync::Arc;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_logger::{DbLogMode, LogFormat, LogOutput, LoggingConnection};
+
+use common::{connection, Lines};
+
+// One test, as the defaults are shared.
+#[test]
+fn established_connections_start_with_the_global_defaults() {
+    let lines = Lines::default();
+    let (mut template, _) = connection(DbLogMode::Standard);
+    template.set_output(Arc::new(LogOutput::writer(lines.clone())));
+    template.set_log_format(LogFormat::Logfmt);
+    template.set_show_seq(true);
+    diesel_logger::set_global_defaults(template.config().clone());
+    diesel_logger::set_global_log_mode(DbLogMode::Standard);
+
+    let mut conn = LoggingConnection::<SqliteConnection>::establish(":memory:").unwrap();
+    lines.clear();
+    conn.execute("SELECT 1").unwrap();
+    // Setters still override the defaults.
+    conn.set_log_format(LogFormat::Text);
+    conn.execute("SELECT 2").unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 2, "{:#?}", lines);
+    assert!(lines[0].contains(r#" sql="SELECT 1" "#), "{}", lines[0]);
+    assert!(lines[0].ends_with(" seq=1"), "{}", lines[0]);
+    assert!(lines[1].starts_with("DEBUG: #2 [SELECT] "), "{}", lines[1]);
+}
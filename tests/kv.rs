@@ -0,0 +1,82 @@
+//! The key-values of the `kv` feature, in their own process as they need the
+//! global logger.
+
+#![cfg(all(feature = "kv", not(feature = "tracing-events")))]
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_logger::{DbLogMode, LoggingConnection};
+
+/// The key-values of a record, rendered.
+#[derive(Clone, Debug, Default)]
+struct Pairs(BTreeMap<String, String>);
+
+static RECORDS: Mutex<Vec<(String, Pairs)>> = Mutex::new(Vec::new());
+
+struct Recorder;
+
+impl<'kvs> log::kv::VisitSource<'kvs> for Pairs {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+impl log::Log for Recorder {
+    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let mut pairs = Pairs::default();
+        record.key_values().visit(&mut pairs).unwrap();
+        let entry = (record.args().to_string(), pairs);
+        RECORDS.lock().unwrap().push(entry);
+    }
+
+    fn flush(&self) {}
+}
+
+// One test, as the records of the global logger are shared.
+#[test]
+fn query_lines_carry_their_fields_as_key_values() {
+    log::set_logger(&Recorder).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    let mut conn = LoggingConnection::new(
+        SqliteConnection::establish(":memory:").unwrap(),
+        DbLogMode::Verbose,
+    );
+    conn.execute("CREATE TABLE t (a INTEGER)").unwrap();
+    conn.set_slow_query_thresholds(
+        DbLogMode::Verbose,
+        diesel_logger::SlowQueryThresholds {
+            slow: std::time::Duration::from_secs(0),
+            very_slow: std::time::Duration::from_secs(3600),
+        },
+    );
+    RECORDS.lock().unwrap().clear();
+    conn.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+
+    let records = RECORDS.lock().unwrap().clone();
+    assert_eq!(records.len(), 1, "{:#?}", records);
+    let (message, pairs) = &records[0];
+    // The human readable line is kept.
+    assert!(
+        message.contains("INSERT INTO t VALUES (1), (2)"),
+        "{}",
+        message
+    );
+    assert_eq!(pairs.0["kind"], "insert");
+    assert_eq!(pairs.0["rows"], "2");
+    assert_eq!(pairs.0["slow"], "true");
+    assert_eq!(pairs.0["raw"], "true");
+    let duration_ms: f64 = pairs.0["duration_ms"].parse().unwrap();
+    assert!(duration_ms >= 0.0, "{:#?}", pairs);
+}
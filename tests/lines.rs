@@ -0,0 +1,268 @@
+//! The wording of the lines logged for queries.
+
+// The `table!` and derive macros of diesel 1.4 implement traits inside functions.
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel_logger::DbLogMode;
+
+use common::connection;
+
+table! {
+    t (a) {
+        a -> Integer,
+    }
+}
+
+/// The `qid=...` token of a line.
+fn query_id(line: &str) -> &str {
+    let start = line.find(" qid=").expect(line) + " qid=".len();
+    &line[start..start + 16]
+}
+
+#[test]
+fn queries_cut_short_are_identified_by_their_full_text() {
+    let (mut conn, lines) = connection(DbLogMode::ExcessiveMini);
+    conn.batch_execute("CREATE TABLE t (a INTEGER, b INTEGER)")
+        .unwrap();
+    conn.set_log_query_id(true);
+    lines.clear();
+
+    let prefix = "SELECT a, b FROM t WHERE a IS NOT NULL AND b IS NOT NULL";
+    conn.execute(&format!("{} ORDER BY a", prefix)).unwrap();
+    conn.execute(&format!("{} ORDER BY b", prefix)).unwrap();
+
+    let lines = lines.all();
+    let queries: Vec<_> = lines.iter().filter(|line| line.contains(" qid=")).collect();
+    assert_eq!(queries.len(), 2, "{:#?}", lines);
+    // Both lines show the same 40 chars.
+    assert!(
+        queries.iter().all(|line| line.contains(&prefix[..40])),
+        "{:#?}",
+        queries
+    );
+    assert!(
+        !queries.iter().any(|line| line.contains("ORDER BY")),
+        "{:#?}",
+        queries
+    );
+    assert_ne!(query_id(queries[0]), query_id(queries[1]));
+}
+
+#[test]
+fn inserts_show_how_many_rows_they_inserted() {
+    let (conn, lines) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    lines.clear();
+    conn.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+    conn.execute("UPDATE t SET a = 3").unwrap();
+
+    let lines = lines.all();
+    assert!(
+        lines[0].starts_with("DEBUG: [INSERT batch_rows=2] Query ran in "),
+        "{:#?}",
+        lines
+    );
+    assert!(
+        lines[1].starts_with("DEBUG: [UPDATE] Query ran in "),
+        "{:#?}",
+        lines
+    );
+}
+
+#[test]
+fn executions_of_the_same_query_share_their_id() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    conn.set_log_query_id(true);
+    lines.clear();
+
+    let _: Vec<i32> = t::table
+        .filter(t::a.eq(1))
+        .select(t::a)
+        .load(&conn)
+        .unwrap();
+    let _: Vec<i32> = t::table
+        .filter(t::a.eq(2))
+        .select(t::a)
+        .load(&conn)
+        .unwrap();
+    let _: Vec<i32> = t::table
+        .filter(t::a.gt(2))
+        .select(t::a)
+        .load(&conn)
+        .unwrap();
+    conn.execute("DELETE FROM t WHERE a = 1").unwrap();
+    conn.execute("DELETE FROM t WHERE a = 2").unwrap();
+
+    let lines = lines.all();
+    let ids: Vec<_> = lines.iter().map(|line| query_id(line)).collect();
+    assert_eq!(ids[0], ids[1], "{:#?}", lines);
+    assert_ne!(ids[0], ids[2], "{:#?}", lines);
+    assert_eq!(ids[3], ids[4], "{:#?}", lines);
+    assert_ne!(ids[0], ids[3], "{:#?}", lines);
+}
+
+#[test]
+fn writes_affecting_no_or_too_many_rows_are_warned_about() {
+    let (mut conn, lines) = connection(DbLogMode::OnError);
+    conn.batch_execute("CREATE TABLE t (a INTEGER); INSERT INTO t VALUES (1), (2), (3)")
+        .unwrap();
+    conn.set_warn_on_zero_affected(true);
+    conn.set_max_affected_rows(Some(2));
+
+    conn.execute("UPDATE t SET a = a WHERE a = 1").unwrap();
+    conn.execute("UPDATE t SET a = a WHERE a = 4").unwrap();
+    conn.execute("DELETE FROM t").unwrap();
+    conn.execute("SELECT * FROM t").unwrap();
+
+    assert_eq!(
+        lines.all(),
+        [
+            "WARN: UPDATE affected no rows: UPDATE t SET a = a WHERE a = 4",
+            "WARN: DELETE affected 3 rows, more than the limit of 2: DELETE FROM t",
+        ]
+    );
+}
+
+#[test]
+fn writes_without_where_are_warned_about_or_refused() {
+    let (mut conn, lines) = connection(DbLogMode::OnError);
+    conn.batch_execute("CREATE TABLE t (a INTEGER); INSERT INTO t VALUES (1)")
+        .unwrap();
+    conn.set_warn_on_missing_where(true);
+
+    conn.execute("UPDATE t SET a = 2 WHERE a = 1").unwrap();
+    conn.execute("UPDATE t SET a = 3").unwrap();
+    assert_eq!(
+        lines.all(),
+        ["WARN: UPDATE without a WHERE clause affects every row: UPDATE t SET a = 3"]
+    );
+
+    lines.clear();
+    conn.set_refuse_missing_where(true);
+    assert!(conn.execute("DELETE FROM t").is_err());
+    assert_eq!(
+        lines.all(),
+        ["ERROR: Refused to run DELETE without a WHERE clause: DELETE FROM t"]
+    );
+    let left: i64 = diesel::select(diesel::dsl::sql::<diesel::sql_types::BigInt>(
+        "(SELECT COUNT(*) FROM t)",
+    ))
+    .get_result(&conn)
+    .unwrap();
+    assert_eq!(left, 1);
+}
+
+#[test]
+fn aggregates_are_reported_on_request() {
+    let (mut conn, lines) = connection(DbLogMode::NoLog);
+    conn.set_collect_aggregates(true);
+    conn.execute("SELECT 1").unwrap();
+    conn.execute("SELECT 2").unwrap();
+    assert_eq!(lines.text(), "");
+
+    conn.set_log_mode(DbLogMode::Standard);
+    let inner: diesel::sqlite::SqliteConnection = conn.into_inner();
+    let report = lines.all();
+    assert_eq!(
+        report[0],
+        "INFO: Query report, top 1 of 1 query shapes by total time:"
+    );
+    assert!(report[1].ends_with(": SELECT ?"), "{:#?}", report);
+    assert!(
+        report[1].trim_start().starts_with("2x, total "),
+        "{:#?}",
+        report
+    );
+    inner.execute("SELECT 1").unwrap();
+}
+
+#[test]
+fn lines_of_sql_query_are_tagged_raw() {
+    #[derive(QueryableByName)]
+    struct Answer {
+        #[sql_type = "diesel::sql_types::Integer"]
+        answer: i32,
+    }
+
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    let run_both = |conn: &diesel_logger::LoggingConnection<_>| {
+        lines.clear();
+        let answers: Vec<Answer> = diesel::sql_query("SELECT 42 AS answer").load(conn).unwrap();
+        assert_eq!(answers[0].answer, 42);
+        let _: Vec<i32> = t::table.select(t::a).load(conn).unwrap();
+        let lines = lines.all();
+        assert_eq!(lines.len(), 2, "{:#?}", lines);
+        lines
+    };
+
+    conn.set_log_format(diesel_logger::LogFormat::Logfmt);
+    let logged = run_both(&conn);
+    assert!(logged[0].ends_with(" raw=true"), "{}", logged[0]);
+    assert!(!logged[1].contains("raw="), "{}", logged[1]);
+
+    // Human readable lines are only tagged on request.
+    conn.set_log_format(diesel_logger::LogFormat::Text);
+    let logged = run_both(&conn);
+    assert!(logged[0].starts_with("DEBUG: [SELECT] "), "{}", logged[0]);
+    conn.set_mark_raw_queries(true);
+    let logged = run_both(&conn);
+    assert!(
+        logged[0].starts_with("DEBUG: [raw] [SELECT] "),
+        "{}",
+        logged[0]
+    );
+    assert!(logged[1].starts_with("DEBUG: [SELECT] "), "{}", logged[1]);
+}
+
+#[test]
+fn context_pairs_follow_every_line_in_order() {
+    let (conn, lines) = connection(DbLogMode::Standard);
+    let mut conn = conn
+        .with_context("service", "billing")
+        .with_context("region", r#"eu "west""#);
+    conn.execute("SELECT 1").unwrap();
+    conn.set_log_format(diesel_logger::LogFormat::Logfmt);
+    conn.execute("SELECT 2").unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 2, "{:#?}", lines);
+    assert!(
+        lines[0].ends_with(r#": SELECT 1 service=billing region=eu "west""#),
+        "{}",
+        lines[0]
+    );
+    assert!(
+        lines[1].contains(r#" sql="SELECT 2" service="billing" region="eu \"west\"" "#),
+        "{}",
+        lines[1]
+    );
+}
+
+#[test]
+fn queries_logged_before_they_run_are_logged_again_after() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.set_log_before_execute(true);
+    conn.execute("SELECT 1").unwrap();
+    conn.execute("SELECT 2").unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 4, "{:#?}", lines);
+    for (pair, query) in lines.chunks(2).zip(&["SELECT 1", "SELECT 2"]) {
+        assert_eq!(pair[0], format!("DEBUG: Running query: {}", query));
+        assert!(
+            pair[1].starts_with("DEBUG: [SELECT] Query ran in "),
+            "{}",
+            pair[1]
+        );
+        assert!(pair[1].ends_with(query), "{}", pair[1]);
+    }
+}
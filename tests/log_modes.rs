@@ -0,0 +1,256 @@
+//! What each `DbLogMode` logs.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use diesel_logger::{DbLogMode, SlowQueryThresholds};
+
+use common::connection;
+
+/// The level of the line of `SELECT 1`, with every query being very slow.
+fn level(mode: DbLogMode, multiplier: Option<u32>) -> String {
+    let (mut conn, lines) = connection(mode);
+    conn.set_default_slow_query_thresholds(SlowQueryThresholds {
+        slow: Duration::from_nanos(1),
+        very_slow: Duration::from_nanos(1),
+    });
+    conn.set_critical_multiplier(multiplier);
+    conn.execute("SELECT 1").unwrap();
+    let line = lines.all().remove(0);
+    line[..line.find(':').unwrap()].to_owned()
+}
+
+#[test]
+fn on_error_only_logs_failed_queries() {
+    let (conn, lines) = connection(DbLogMode::OnError);
+    conn.batch_execute("CREATE TABLE t (a INTEGER NOT NULL)")
+        .unwrap();
+    conn.execute("INSERT INTO t VALUES (1)").unwrap();
+    let _: Vec<i32> = diesel::select(sql::<Integer>("1")).load(&conn).unwrap();
+    assert_eq!(lines.text(), "");
+
+    assert!(conn.execute("INSERT INTO t VALUES (NULL)").is_err());
+    assert!(conn.batch_execute("SELECT * FROM missing").is_err());
+    let failed: QueryResult<Vec<i32>> =
+        diesel::select(sql::<Integer>("a FROM missing")).load(&conn);
+    assert!(failed.is_err());
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 3, "{:#?}", lines);
+    assert!(
+        lines[0].starts_with("ERROR: Query failed after "),
+        "{:#?}",
+        lines
+    );
+    assert!(lines[0].ends_with("NOT NULL constraint failed: t.a`: INSERT INTO t VALUES (NULL)"));
+    assert!(lines[1].ends_with("no such table: missing`: SELECT * FROM missing"));
+    assert!(lines[2].contains("SELECT a FROM missing"));
+}
+
+#[test]
+fn queries_are_captured_without_being_logged() {
+    let (conn, lines) = connection(DbLogMode::NoLog);
+    let (rows, queries) = conn.capture_queries(|| {
+        conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1)").unwrap()
+    });
+    assert_eq!(rows, 1);
+    assert_eq!(
+        queries,
+        ["CREATE TABLE t (a INTEGER)", "INSERT INTO t VALUES (1)"]
+    );
+    assert_eq!(lines.text(), "");
+}
+
+#[test]
+fn queries_far_over_the_warn_threshold_are_errors() {
+    for &mode in &[DbLogMode::Standard, DbLogMode::Excessive] {
+        assert_eq!(level(mode, Some(1)), "ERROR");
+        assert_eq!(level(mode, Some(u32::MAX)), "WARN");
+        assert_eq!(level(mode, None), "WARN");
+    }
+}
+
+#[test]
+fn excessive_mini_cuts_queries_between_chars() {
+    let (conn, lines) = connection(DbLogMode::ExcessiveMini);
+    let query = format!("SELECT '{}'", "ö".repeat(40));
+    conn.execute(&query).unwrap();
+    let text = lines.text();
+    let cut = format!("SELECT '{}", "ö".repeat(32));
+    assert!(text.contains(&cut), "{}", text);
+    assert!(!text.contains(&format!("{}ö", cut)), "{}", text);
+}
+
+#[test]
+fn excessive_mini_cuts_queries_after_redacting_them() {
+    let (mut conn, lines) = connection(DbLogMode::ExcessiveMini);
+    conn.batch_execute("CREATE TABLE t (a INTEGER, b TEXT)")
+        .unwrap();
+    // The secret starts before the 40th char and ends after it.
+    let query = "SELECT a FROM t WHERE a = 1 AND b = 'hunter2-secret-value'";
+    lines.clear();
+    conn.execute(query).unwrap();
+    assert!(lines.text().contains("'hun"), "{}", lines.text());
+
+    conn.set_redact_literals(true);
+    lines.clear();
+    conn.execute(query).unwrap();
+    let text = lines.text();
+    assert!(
+        text.contains("SELECT a FROM t WHERE a = ? AND b = ?"),
+        "{}",
+        text
+    );
+    assert!(!text.contains("hun"), "{}", text);
+}
+
+#[test]
+fn queries_run_without_logging_are_not_logged() {
+    let (conn, lines) = connection(DbLogMode::Standard);
+    let inserted = conn.without_logging(|| {
+        conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1)").unwrap()
+    });
+    assert_eq!(inserted, 1);
+    assert_eq!(lines.text(), "");
+
+    conn.execute("SELECT 1").unwrap();
+    assert_eq!(lines.all().len(), 1);
+}
+
+#[test]
+fn profile_lines_have_fixed_fields_and_no_sql() {
+    let (conn, lines) = connection(DbLogMode::Profile);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    lines.clear();
+    conn.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+    conn.execute("INSERT INTO t VALUES (3), (4)").unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 2, "{:#?}", lines);
+    for line in &lines {
+        let fields: Vec<_> = line
+            .trim_start_matches("INFO: ")
+            .split(' ')
+            .map(|field| field.split('=').next().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            ["fp", "qid", "kind", "duration_us", "rows"],
+            "{}",
+            line
+        );
+        assert!(line.contains(" kind=insert "), "{}", line);
+        assert!(line.ends_with(" rows=2"), "{}", line);
+    }
+    let fp = |line: &str| line.split(' ').nth(1).unwrap().to_owned();
+    assert_eq!(fp(&lines[0]), fp(&lines[1]));
+}
+
+#[test]
+fn the_log_predicate_decides_which_queries_are_logged() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.set_log_predicate(Some(Arc::new(|query: &str| !query.contains("secret"))));
+
+    conn.batch_execute("CREATE TABLE secret (a INTEGER)")
+        .unwrap();
+    conn.execute("SELECT 1").unwrap();
+    assert!(conn.execute("SELECT * FROM missing_secret").is_err());
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 1, "{:#?}", lines);
+    assert!(lines[0].ends_with(": SELECT 1"), "{:#?}", lines);
+}
+
+#[test]
+fn the_next_query_can_be_forced_into_a_mode() {
+    let (conn, lines) = connection(DbLogMode::NoLog);
+    diesel_logger::log_next_query_as(DbLogMode::Verbose);
+    conn.execute("SELECT 1").unwrap();
+    conn.execute("SELECT 2").unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 1, "{:#?}", lines);
+    assert!(lines[0].ends_with(": SELECT 1"), "{}", lines[0]);
+}
+
+#[test]
+fn the_next_query_can_be_suppressed() {
+    let (conn, lines) = connection(DbLogMode::Verbose);
+    diesel_logger::suppress_next_query();
+    conn.execute("SELECT 1").unwrap();
+    conn.execute("SELECT 2").unwrap();
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 1, "{:#?}", lines);
+    assert!(lines[0].ends_with(": SELECT 2"), "{}", lines[0]);
+}
+
+#[test]
+fn only_failed_migration_queries_are_logged_when_suppressed() {
+    use diesel::migration::MigrationConnection;
+
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.set_suppress_migration_logs(true);
+    conn.run_as_migrations(|| {
+        conn.setup().unwrap();
+        conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1)").unwrap();
+        assert!(conn.batch_execute("CREATE TABLE t (a INTEGER)").is_err());
+    });
+    let migrations = lines.all();
+    assert_eq!(migrations.len(), 1, "{:#?}", migrations);
+    assert!(
+        migrations[0].starts_with("ERROR: Query failed after "),
+        "{}",
+        migrations[0]
+    );
+
+    lines.clear();
+    conn.execute("SELECT 1").unwrap();
+    let after = lines.all();
+    assert_eq!(after.len(), 1, "{:#?}", after);
+    assert!(after[0].starts_with("DEBUG: [SELECT] "), "{}", after[0]);
+}
+
+/// A clock stopped at the given hour UTC on the first day of 1970.
+#[cfg(feature = "chrono")]
+#[derive(Debug)]
+struct At(u64);
+
+#[cfg(feature = "chrono")]
+impl diesel_logger::TimeSource for At {
+    fn now(&self) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + Duration::from_secs(self.0 * 3600)
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn only_queries_inside_the_active_window_are_logged() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    let hour = |hour| chrono::NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+    let mut logged_at = |start, end| {
+        conn.set_active_window(Some((hour(start), hour(end))));
+        lines.clear();
+        for &now in &[1, 9, 10, 11, 12, 23] {
+            conn.set_time_source(Arc::new(At(now)));
+            conn.execute(&format!("SELECT {}", now)).unwrap();
+        }
+        lines
+            .all()
+            .iter()
+            .map(|line| line.rsplit(": ").next().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    // The end of the window is not part of it.
+    assert_eq!(logged_at(10, 12), ["SELECT 10", "SELECT 11"]);
+    assert_eq!(logged_at(22, 2), ["SELECT 1", "SELECT 23"]);
+}
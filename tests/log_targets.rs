@@ -0,0 +1,80 @@
+// With `tracing-events`, lines are `tracing` events without these targets.
+#![cfg(not(feature = "tracing-events"))]
+
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_logger::{DbLogMode, LoggingConnection};
+
+static RECORDS: Mutex<Vec<(String, log::Level, String)>> = Mutex::new(Vec::new());
+
+struct Recorder;
+
+impl log::Log for Recorder {
+    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let entry = (
+            record.target().to_owned(),
+            record.level(),
+            record.args().to_string(),
+        );
+        RECORDS.lock().unwrap().push(entry);
+    }
+
+    fn flush(&self) {}
+}
+
+fn logged<T>(run: impl FnOnce() -> T) -> Vec<(String, log::Level, String)> {
+    let _ = log::set_logger(&Recorder);
+    log::set_max_level(log::LevelFilter::Trace);
+    RECORDS.lock().unwrap().clear();
+    run();
+    RECORDS.lock().unwrap().clone()
+}
+
+// One test, as the records of the global logger are shared.
+#[test]
+fn every_line_is_logged_under_a_target_of_the_crate() {
+    diesel_logger::set_global_log_mode(DbLogMode::Standard);
+    let records = logged(|| {
+        let conn = LoggingConnection::new(
+            SqliteConnection::establish(":memory:").unwrap(),
+            DbLogMode::Standard,
+        );
+        conn.execute("CREATE TABLE t (a INTEGER)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1)").unwrap();
+        conn.execute("SELECT 1").unwrap();
+    });
+    let targets: Vec<_> = records.iter().map(|(target, _, _)| &**target).collect();
+    assert_eq!(
+        targets,
+        [
+            "diesel_logger::ddl",
+            "diesel_logger::write",
+            "diesel_logger::select"
+        ]
+    );
+
+    diesel_logger::set_global_log_mode(DbLogMode::Standard);
+    let records = logged(|| {
+        let conn = LoggingConnection::<SqliteConnection>::establish(
+            "/nonexistent/dir/db.sqlite?password=secret",
+        );
+        assert!(conn.is_err());
+    });
+    assert_eq!(records.len(), 1, "{:#?}", records);
+    let (target, level, message) = &records[0];
+    assert_eq!(
+        (&**target, *level),
+        ("diesel_logger::other", log::Level::Error)
+    );
+    assert!(
+        message.starts_with("Failed to connect to /nonexistent/dir/db.sqlite?password=*** after "),
+        "{}",
+        message
+    );
+}
@@ -0,0 +1,42 @@
+//! The presets named by `GST_DATABASE_LOGGING`, in their own process as they
+//! change the environment.
+
+mod common;
+
+use diesel::prelude::*;
+use diesel_logger::DbLogMode;
+
+use common::connection;
+
+/// The lines of two fast queries on a connection created with
+/// `GST_DATABASE_LOGGING=preset`.
+fn lines(preset: &str) -> Vec<String> {
+    std::env::set_var("GST_DATABASE_LOGGING", preset);
+    assert_eq!(DbLogMode::from_env(), DbLogMode::Standard);
+    let (conn, lines) = connection(DbLogMode::Standard);
+    conn.execute("SELECT 1 WHERE 'secret' = 'secret'").unwrap();
+    conn.execute("SELECT 2").unwrap();
+    lines.all()
+}
+
+// One test, as the environment is shared.
+#[test]
+fn new_connections_adopt_the_preset_named_by_the_environment() {
+    for &preset in &["dev", "Development"] {
+        let lines = lines(preset);
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert!(lines[0].starts_with("INFO: #1 [SELECT] "), "{}", lines[0]);
+        assert!(lines[0].contains("'secret'"), "{}", lines[0]);
+    }
+    for &preset in &["prod", "PRODUCTION"] {
+        // The second query is sampled out.
+        let lines = lines(preset);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(lines[0].starts_with("TRACE: "), "{}", lines[0]);
+        assert!(lines[0].contains(" qid="), "{}", lines[0]);
+        assert!(lines[0].contains("SELECT ? WHERE ? = ?"), "{}", lines[0]);
+    }
+    let lines = lines("standard");
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+    assert!(lines[0].starts_with("DEBUG: [SELECT] "), "{}", lines[0]);
+}
@@ -0,0 +1,115 @@
+//! What a connection reports about the queries it ran.
+
+mod common;
+
+use std::time::Duration;
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use diesel_logger::{DbLogMode, SlowQueryThresholds};
+
+use common::connection;
+
+/// Thresholds which make queries slow from `slow` on, and never very slow.
+fn slow_from(slow: Duration) -> SlowQueryThresholds {
+    SlowQueryThresholds {
+        slow,
+        very_slow: Duration::from_secs(3600),
+    }
+}
+
+#[test]
+fn the_total_query_time_accumulates_until_it_is_taken() {
+    let (conn, _) = connection(DbLogMode::NoLog);
+    assert_eq!(conn.total_query_time(), Duration::from_secs(0));
+
+    let mut expected = Duration::from_secs(0);
+    for _ in 0..3 {
+        let (_, duration): (Vec<i32>, _) = conn
+            .load_timed(diesel::select(sql::<Integer>("1")))
+            .unwrap();
+        expected += duration;
+        assert_eq!(conn.total_query_time(), expected);
+    }
+    assert_eq!(conn.read_total_time(), expected);
+
+    assert_eq!(conn.take_total_query_time(), expected);
+    assert_eq!(conn.total_query_time(), Duration::from_secs(0));
+    assert_eq!(conn.query_count(), 3);
+
+    let (_, duration): (Vec<i32>, _) = conn
+        .load_timed(diesel::select(sql::<Integer>("1")))
+        .unwrap();
+    assert_eq!(conn.total_query_time(), duration);
+}
+
+#[test]
+fn the_slow_flag_follows_the_last_query() {
+    let (mut conn, _) = connection(DbLogMode::Standard);
+    assert!(!conn.last_query_was_slow());
+
+    conn.set_default_slow_query_thresholds(slow_from(Duration::from_secs(60)));
+    conn.execute("SELECT 1").unwrap();
+    assert!(!conn.last_query_was_slow());
+
+    conn.set_default_slow_query_thresholds(slow_from(Duration::from_nanos(1)));
+    conn.execute("SELECT 2").unwrap();
+    assert!(conn.last_query_was_slow());
+
+    conn.set_default_slow_query_thresholds(slow_from(Duration::from_secs(60)));
+    conn.execute("SELECT 3").unwrap();
+    assert!(!conn.last_query_was_slow());
+}
+
+#[test]
+fn the_last_executed_sql_is_kept_after_an_error() {
+    let (mut conn, _) = connection(DbLogMode::NoLog);
+    conn.set_track_last_sql(true);
+    conn.execute("SELECT 1").unwrap();
+    assert_eq!(
+        diesel_logger::last_executed_sql().as_deref(),
+        Some("SELECT 1")
+    );
+
+    assert!(conn.execute("SELECT * FROM missing").is_err());
+    assert_eq!(
+        diesel_logger::last_executed_sql().as_deref(),
+        Some("SELECT * FROM missing")
+    );
+
+    // Untracked connections leave it alone.
+    conn.set_track_last_sql(false);
+    conn.execute("SELECT 2").unwrap();
+    assert_eq!(
+        diesel_logger::last_executed_sql().as_deref(),
+        Some("SELECT * FROM missing")
+    );
+}
+
+#[test]
+fn the_health_summary_counts_the_queries_of_the_window() {
+    let (mut conn, _) = connection(DbLogMode::NoLog);
+    assert_eq!(conn.health_summary(), None);
+    conn.set_health_window(Some(Duration::from_secs(60)));
+
+    conn.set_default_slow_query_thresholds(slow_from(Duration::from_secs(60)));
+    conn.execute("SELECT 1").unwrap();
+    assert!(conn.execute("SELECT * FROM missing").is_err());
+    conn.set_default_slow_query_thresholds(slow_from(Duration::from_nanos(1)));
+    conn.execute("SELECT 2").unwrap();
+    conn.execute("SELECT 3").unwrap();
+    assert!(conn.execute("SELECT * FROM missing").is_err());
+
+    let summary = conn.health_summary().unwrap();
+    assert_eq!(summary.window, Duration::from_secs(60));
+    assert_eq!(summary.queries, 5);
+    assert_eq!(summary.errors, 2);
+    assert_eq!(summary.slow_queries, 3);
+    assert!(summary.p95.is_some());
+    // Taking a summary does not reset the window.
+    assert_eq!(conn.health_summary().unwrap().queries, 5);
+
+    conn.set_health_window(None);
+    assert_eq!(conn.health_summary(), None);
+}
@@ -0,0 +1,135 @@
+//! Queries run under a `tracing` subscriber.
+
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use diesel::prelude::*;
+use diesel_logger::DbLogMode;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use common::connection;
+
+/// The fields of an event, rendered.
+type Fields = HashMap<String, String>;
+
+/// A subscriber which keeps track of the entered spans and records events.
+#[derive(Default)]
+struct Recorder {
+    last_id: AtomicU64,
+    spans: Mutex<HashMap<u64, &'static Metadata<'static>>>,
+    entered: Mutex<Vec<u64>>,
+    events: Arc<Mutex<Vec<(tracing::Level, Fields)>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl tracing::field::Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.spans.lock().unwrap().insert(id, span.metadata());
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        let level = *event.metadata().level();
+        self.events.lock().unwrap().push((level, fields));
+    }
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> tracing_core::span::Current {
+        match self.entered.lock().unwrap().last() {
+            Some(&id) => {
+                let metadata = self.spans.lock().unwrap()[&id];
+                tracing_core::span::Current::new(Id::from_u64(id), metadata)
+            }
+            None => tracing_core::span::Current::none(),
+        }
+    }
+}
+
+#[test]
+fn queries_inside_a_span_are_stamped_with_it() {
+    let (conn, lines) = connection(DbLogMode::Standard);
+    tracing::subscriber::with_default(Recorder::default(), || {
+        conn.execute("SELECT 1").unwrap();
+        let span = tracing::info_span!("http_request");
+        let _entered = span.enter();
+        conn.execute("SELECT 2").unwrap();
+    });
+
+    let lines = lines.all();
+    assert_eq!(lines.len(), 2, "{:#?}", lines);
+    assert!(lines[0].starts_with("DEBUG: [SELECT] "), "{}", lines[0]);
+    assert!(
+        lines[1].starts_with("DEBUG: [req:http_request#1] [SELECT] "),
+        "{}",
+        lines[1]
+    );
+}
+
+#[cfg(feature = "tracing-events")]
+#[test]
+fn lines_are_emitted_as_events_with_fields() {
+    use diesel::sqlite::SqliteConnection;
+    use diesel_logger::LoggingConnection;
+
+    let conn = LoggingConnection::new(
+        SqliteConnection::establish(":memory:").unwrap(),
+        DbLogMode::Standard,
+    );
+    let recorder = Recorder::default();
+    let events = recorder.events.clone();
+    tracing::subscriber::with_default(recorder, || {
+        conn.execute("CREATE TABLE t (a INTEGER)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+    });
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2, "{:#?}", events);
+    let (level, fields) = &events[1];
+    assert_eq!(*level, tracing::Level::DEBUG);
+    assert!(
+        fields["message"].ends_with(": INSERT INTO t VALUES (1), (2)"),
+        "{:#?}",
+        fields
+    );
+    assert_eq!(fields["kind"], "insert");
+    assert_eq!(fields["rows"], "2");
+    assert_eq!(fields["slow"], "false");
+    assert!(fields.contains_key("duration_ms"), "{:#?}", fields);
+}
@@ -0,0 +1,146 @@
+//! Transactions run through the connection, including the test helpers.
+
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel::sqlite::SqliteConnection;
+use diesel_logger::{DbLogMode, LoggingConnection};
+
+use common::connection;
+
+fn count(conn: &LoggingConnection<SqliteConnection>) -> i64 {
+    diesel::select(sql::<BigInt>("(SELECT COUNT(*) FROM t)"))
+        .get_result(conn)
+        .unwrap()
+}
+
+#[test]
+fn test_transactions_are_rolled_back() {
+    let (conn, _) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+
+    let inserted = conn.test_transaction(|| {
+        conn.execute("INSERT INTO t VALUES (1)")?;
+        Ok(count(&conn))
+    });
+    assert_eq!(inserted, 1);
+    assert_eq!(count(&conn), 0);
+}
+
+#[test]
+fn test_transactions_are_rolled_back_on_panic() {
+    let (conn, _) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        conn.test_transaction::<()>(|| {
+            conn.execute("INSERT INTO t VALUES (1)")?;
+            panic!("the test failed");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(count(&conn), 0);
+    conn.transaction(|| conn.execute("INSERT INTO t VALUES (2)"))
+        .unwrap();
+    assert_eq!(count(&conn), 1);
+}
+
+#[test]
+fn only_queries_deep_enough_in_transactions_are_logged() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    conn.set_min_transaction_depth(Some(2));
+
+    conn.execute("INSERT INTO t VALUES (0)").unwrap();
+    conn.transaction(|| {
+        conn.execute("INSERT INTO t VALUES (1)")?;
+        conn.transaction(|| conn.execute("INSERT INTO t VALUES (2)"))
+    })
+    .unwrap();
+
+    let inserts: Vec<_> = lines
+        .all()
+        .into_iter()
+        .filter(|line| line.contains("INSERT"))
+        .collect();
+    assert_eq!(inserts.len(), 1, "{:#?}", inserts);
+    assert!(
+        inserts[0].ends_with("INSERT INTO t VALUES (2)"),
+        "{:#?}",
+        inserts
+    );
+
+    lines.clear();
+    conn.set_min_transaction_depth(None);
+    conn.execute("INSERT INTO t VALUES (3)").unwrap();
+    assert_eq!(lines.all().len(), 1);
+}
+
+#[test]
+fn committed_transactions_are_summarized_in_one_line() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    conn.set_summarize_transactions(true);
+    lines.clear();
+
+    conn.transaction(|| {
+        conn.execute("INSERT INTO t VALUES (1)")?;
+        conn.execute("INSERT INTO t VALUES (2)")
+    })
+    .unwrap();
+
+    let summaries: Vec<_> = lines
+        .all()
+        .into_iter()
+        .filter(|line| line.contains("Transaction committed"))
+        .collect();
+    assert_eq!(summaries.len(), 1, "{:#?}", summaries);
+    assert!(
+        summaries[0].starts_with("INFO: Transaction committed: queries=2 "),
+        "{:#?}",
+        summaries
+    );
+}
+
+#[test]
+fn commits_are_timed() {
+    let (mut conn, lines) = connection(DbLogMode::Standard);
+    conn.batch_execute("CREATE TABLE t (a INTEGER)").unwrap();
+    let commits = Arc::new(Mutex::new(Vec::new()));
+    let recorded = commits.clone();
+    conn.set_on_commit(Some(Arc::new(move |duration| {
+        recorded.lock().unwrap().push(duration)
+    })));
+    let insert = |conn: &LoggingConnection<SqliteConnection>| {
+        conn.transaction(|| conn.execute("INSERT INTO t VALUES (1)"))
+            .unwrap();
+    };
+
+    conn.set_slow_commit_threshold(Some(Duration::from_secs(60)));
+    lines.clear();
+    insert(&conn);
+    assert!(!lines.text().contains("commit"), "{}", lines.text());
+
+    conn.set_slow_commit_threshold(Some(Duration::from_secs(0)));
+    lines.clear();
+    insert(&conn);
+    let commit_lines: Vec<_> = lines
+        .all()
+        .into_iter()
+        .filter(|line| line.contains("commit"))
+        .collect();
+    assert_eq!(commit_lines.len(), 1, "{:#?}", lines.all());
+    assert!(
+        commit_lines[0].starts_with("WARN: Slow commit took "),
+        "{}",
+        commit_lines[0]
+    );
+    assert_eq!(commits.lock().unwrap().len(), 2);
+}